@@ -19,13 +19,12 @@ use daggy::{NodeIndex, Walker};
 use daggy::petgraph::EdgeDirection;
 
 use texturegen::palette::Srgba;
-use texturegen::{port, Col, Generator};
-use texturegen::process::{BlendType, Constant, EdgeDetect, EdgeDetectType, Invert, Noise, Process,
-                          Select, Setting, SettingMut, Stripes, VoronoiNoise};
-use texturegen::process::Blend as BlendProcess;
+use texturegen::{port, Generator};
+use texturegen::process::{Kind, Process, Value};
 
 use {input_pos, output_pos, Mat, Node, Selection, SimContext, Vect};
 use graphics::RenderContext;
+use passes;
 use State::*;
 use math::*;
 
@@ -96,32 +95,16 @@ pub fn handle(
                         ctx.state = None;
                     },
                     Key::Return => {
-                        use texturegen::process::SettingMut::*;
                         if let Some(Writing) = ctx.state {
                             if let Some(selected) = ctx.selected {
                                 if let Selection::Setting(n, i) = selected {
                                     let mut n = gen.get_process_mut(n).unwrap();
-                                    let setting = n.settings()[i];
-                                    match n.setting_mut(setting) {
-                                        Text(t) => {
-                                            *t = ctx.text.to_lowercase();
+                                    let descriptor = n.settings().swap_remove(i);
+                                    let value = decode_value(descriptor.kind(), &ctx.text);
+                                    if let Some(value) = value {
+                                        if let Err(e) = n.set(descriptor.name, value) {
+                                            println!("{}", e);
                                         }
-                                        Integer(i) => {
-                                            if let Ok(ii) = ctx.text.parse() {
-                                                *i = ii;
-                                            }
-                                        }
-                                        Float(f) => {
-                                            if let Ok(ff) = ctx.text.parse() {
-                                                *f = ff;
-                                            }
-                                        }
-                                        Color(c) => {
-                                            if let Ok(col) = decode_color(&ctx.text) {
-                                                *c = col.into();
-                                            }
-                                        }
-                                        _ => {}
                                     }
                                 }
                                 ctx.text.clear();
@@ -189,6 +172,16 @@ pub fn handle(
                                 ..Default::default()
                             };
                             let (width, height) = (1024, 1024); // TODO: Way to specify these.
+                            let rendered = gen.schedule(port(selected, 0))
+                                .map_err(|e| e.to_string())
+                                .and_then(|s| passes::render(display, &s, width, height));
+                            let rendered = match rendered {
+                                Ok(rendered) => rendered,
+                                Err(e) => {
+                                    println!("{}", e);
+                                    return;
+                                }
+                            };
                             let texture = SrgbTexture2d::empty_with_format(
                                 display,
                                 SrgbFormat::U8U8U8U8,
@@ -197,8 +190,6 @@ pub fn handle(
                                 height,
                             ).unwrap();
                             let mut target = SimpleFrameBuffer::new(display, &texture).unwrap();
-                            let program = gen.get(selected).unwrap().1.shader.borrow();
-                            let program = program.as_ref().expect("Node didn't have shader.");
                             let mut matrix = Mat::identity();
                             matrix.m14 = -1.;
                             matrix.m24 = -1.;
@@ -206,13 +197,15 @@ pub fn handle(
                             matrix.m22 = 2.;
                             let uniforms = uniform! {
                                 matrix: *matrix.as_ref(),
+                                tex: &rendered,
                             };
                             let model = rctx.models.get("node").unwrap();
+                            let program = rctx.programs.get("texture").unwrap();
                             target
                                 .draw(
                                     &model.vertices,
                                     &model.indices,
-                                    &program,
+                                    program,
                                     &uniforms,
                                     &draw_params,
                                 )
@@ -232,52 +225,14 @@ pub fn handle(
                             buffer.save(p.with_extension("png")).ok().unwrap();
                         }
                     },
-                    Key::Key1 => if let None = ctx.state {
-                        let node = gen.add(
-                            Constant::new(Col::new(1., 1., 1., 1.)),
-                            Node::new(ctx.mouse_pos),
-                        );
-                        ctx.selected = Some(Selection::Node(node));
-                    },
-                    Key::Key2 => if let None = ctx.state {
-                        let node = gen.add(
-                            BlendProcess::new(BlendType::Screen, BlendType::Normal),
-                            Node::new(ctx.mouse_pos),
-                        );
-                        ctx.selected = Some(Selection::Node(node));
-                    },
-                    Key::Key3 => if let None = ctx.state {
-                        let node = gen.add(
-                            Stripes::new(4, 1, Col::new(1., 1., 1., 1.), Col::new(0., 0., 0., 1.)),
-                            Node::new(ctx.mouse_pos),
-                        );
-                        ctx.selected = Some(Selection::Node(node));
-                    },
-                    Key::Key4 => if let None = ctx.state {
-                        let node = gen.add(
-                            EdgeDetect::new(0.5, EdgeDetectType::Sobel),
-                            Node::new(ctx.mouse_pos),
-                        );
-                        ctx.selected = Some(Selection::Node(node));
-                    },
-                    Key::Key5 => if let None = ctx.state {
-                        let node = gen.add(Noise::new(0, 2, 2), Node::new(ctx.mouse_pos));
-                        ctx.selected = Some(Selection::Node(node));
-                    },
-                    Key::Key6 => if let None = ctx.state {
-                        let node = gen.add(
-                            VoronoiNoise::new(0, 10, 10, 1., 1.),
-                            Node::new(ctx.mouse_pos),
-                        );
-                        ctx.selected = Some(Selection::Node(node));
-                    },
-                    Key::Key7 => if let None = ctx.state {
-                        let node = gen.add(Select::new(0.5), Node::new(ctx.mouse_pos));
-                        ctx.selected = Some(Selection::Node(node));
-                    },
-                    Key::Key8 => if let None = ctx.state {
-                        let node = gen.add(Invert::new(), Node::new(ctx.mouse_pos));
-                        ctx.selected = Some(Selection::Node(node));
+                    Key::Key1 | Key::Key2 | Key::Key3 | Key::Key4 | Key::Key5 | Key::Key6
+                    | Key::Key7 | Key::Key8 | Key::Key9 => if let None = ctx.state {
+                        let index = k as usize - Key::Key1 as usize;
+                        let process = ctx.registry.iter().nth(index).map(|e| e.create());
+                        if let Some(process) = process {
+                            let node = gen.add(process, Node::new(ctx.mouse_pos));
+                            ctx.selected = Some(Selection::Node(node));
+                        }
                     },
                     _ => {}
                 },
@@ -348,20 +303,13 @@ pub fn handle(
                             }
                             Some(Selection::Setting(n, i)) => {
                                 let (node, _) = gen.get(n).expect("Selected node didn't exist.");
-                                ctx.text = node.setting(node.settings()[i]).to_string();
+                                ctx.text = node.setting(node.settings()[i].name)
+                                    .map(|s| s.to_string())
+                                    .unwrap_or_default();
                                 ctx.caret = ctx.text.len();
                                 ctx.selected = Some(Selection::Setting(n, i));
                                 ctx.state = Some(Writing);
                             }
-                            Some(Selection::Choice(n, i, j)) => {
-                                let mut node =
-                                    gen.get_process_mut(n).expect("Selected node didn't exist.");
-                                let setting = node.settings()[i];
-                                if let SettingMut::Blend(t) = node.setting_mut(setting) {
-                                    *t = BlendType::iter_variants().skip(j).next().unwrap();
-                                }
-                                ctx.selected = Some(Selection::Node(n));
-                            }
                             _ => {}
                         }
                     }
@@ -409,6 +357,17 @@ impl From<ParseFloatError> for DecodeError {
     }
 }
 
+fn decode_value(kind: Kind, s: &str) -> Option<Value> {
+    Some(match kind {
+        Kind::Text => Value::Text(s.to_lowercase()),
+        Kind::Integer => Value::Integer(s.parse().ok()?),
+        Kind::Boolean => Value::Boolean(s == "1" || s == "true"),
+        Kind::Float => Value::Float(s.parse().ok()?),
+        Kind::Color => Value::Color(decode_color(s).ok()?.into()),
+        Kind::Choice | Kind::Stops | Kind::Points => return None,
+    })
+}
+
 fn decode_color(s: &str) -> Result<Srgba, DecodeError> {
     let input = s.split(",").collect::<Vec<_>>();
     if input.len() < 4 {
@@ -437,51 +396,14 @@ fn find_selected(
             let settings = n.settings();
             let size = 23.;
 
-            if let Selection::Setting(_, j) = s {
-                let pos = Vect::new(-0.5, -0.5 + (j as f32) / 20. * 0.5);
-                let mut string = settings[j].to_string();
-                string.push_str(": ");
-                match n.setting(settings[j]) {
-                    Setting::Blend(_) => {
-                        let bb = rctx.font_renderer
-                            .bounding_box(&rctx.fonts, "anka", size, &string)
-                            .unwrap();
-                        let max = from_window_to_screen(dims, [bb.max.x, bb.max.y]);
-                        let pos = pos + Vect::new(max.x * 1.25, 1. / 20. * 0.5);
-                        for (choice, blend) in BlendType::iter_variants().enumerate() {
-                            let blend = format!("{:?}", blend);
-                            let pos = pos + Vect::new(0., (choice as f32 / 20.) * 0.5);
-                            let pos = from_screen_to_world(rctx.cam, pos);
-                            if let Some(bb) =
-                                rctx.font_renderer
-                                    .bounding_box(&rctx.fonts, "anka", size, &blend)
-                            {
-                                let min = from_screen_to_world(
-                                    rctx.cam,
-                                    from_window_to_screen(dims, [bb.min.x, bb.min.y]),
-                                );
-                                let max = from_screen_to_world(
-                                    rctx.cam,
-                                    from_window_to_screen(dims, [bb.max.x, bb.max.y]),
-                                );
-                                if pos.x + min.x < mouse_pos.x && mouse_pos.x < pos.x + max.x {
-                                    if pos.y + min.y < mouse_pos.y && mouse_pos.y < pos.y + max.y {
-                                        return Some(Selection::Choice(i, j, choice));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            }
-
             for (j, setting) in settings.iter().enumerate() {
                 let pos = Vect::new(-0.5, -0.5 + (j as f32) / 20. * 0.5);
                 let pos = from_screen_to_world(rctx.cam, pos);
-                let mut text = setting.to_string();
+                let mut text = setting.name.to_string();
                 text.push_str(": ");
-                text.push_str(&n.setting(setting).to_string());
+                if let Ok(s) = n.setting(setting.name) {
+                    text.push_str(&s.to_string());
+                }
                 if let Some(bb) = rctx.font_renderer
                     .bounding_box(&rctx.fonts, "anka", size, &text)
                 {
@@ -117,6 +117,34 @@ impl<'a> RenderContext<'a> {
                 }).unwrap(),
         );
 
+        programs.insert(
+            "texture".into(),
+            program!(
+                display,
+                140 => {
+                    vertex: "
+                        #version 140
+                        in vec2 position;
+                        in vec2 tex_coords;
+                        uniform mat4 matrix;
+                        out vec2 v_tex_coords;
+                        void main() {
+                            v_tex_coords = tex_coords;
+                            gl_Position = matrix * vec4(position, 0, 1);
+                        }
+                    ",
+                    fragment: "
+                        #version 140
+                        in vec2 v_tex_coords;
+                        uniform sampler2D tex;
+                        out vec4 color;
+                        void main() {
+                            color = texture(tex, v_tex_coords);
+                        }
+                    "
+                }).unwrap(),
+        );
+
         RenderContext {
             font_renderer,
             fonts,
@@ -8,7 +8,7 @@ use glium::uniforms::{AsUniformValue, Uniforms, UniformsStorage};
 
 use nalgebra::normalize;
 use texturegen::GeneratorView;
-use texturegen::process::{BlendType, Process, Setting};
+use texturegen::process::Process;
 
 use {input_pos, output_pos, Node, Selection, SimContext, Vect};
 use super::{vert, RenderContext, Vertex};
@@ -38,7 +38,6 @@ pub fn render<'a>(
         smooth: None,
         ..Default::default()
     };
-    let dims = display.get_framebuffer_dimensions();
     for (_, data) in gen.iter() {
         let pos = flip_y(data.pos);
         let corner_pos = pos - Vect::new(ctx.node_width, ctx.node_width) * 0.5;
@@ -49,21 +48,13 @@ pub fn render<'a>(
         draw(&mut target, &rctx, "back", "plain", &uniforms, &draw_params);
         let matrix =
             rctx.cam * translation(corner_pos.x + 0.05, corner_pos.y + 0.05) * scale(0.9, 0.9);
-        let program = data.shader.borrow();
-        let program = program.as_ref().expect("Node didn't have shader.");
-        let uniforms = uniform! {
-            matrix: *matrix.as_ref(),
-        };
-        let model = rctx.models.get("node").unwrap();
-        target
-            .draw(
-                &model.vertices,
-                &model.indices,
-                &program,
-                &uniforms,
-                &draw_params,
-            )
-            .expect("Drawing node failed.");
+        if let Some(ref texture) = *data.preview.borrow() {
+            let uniforms = uniform! {
+                matrix: *matrix.as_ref(),
+                tex: texture,
+            };
+            draw(&mut target, &rctx, "node", "texture", &uniforms, &draw_params);
+        }
 
         let mut draw = |things: &[_]| {
             for p in things {
@@ -122,9 +113,11 @@ pub fn render<'a>(
                     continue;
                 }
                 let pos = Vect::new(0., -(i as f32) / 20.);
-                let mut string = setting.to_string();
+                let mut string = setting.name.to_string();
                 string.push_str(": ");
-                string.push_str(&node.setting(setting).to_string());
+                if let Ok(s) = node.setting(setting.name) {
+                    string.push_str(&s.to_string());
+                }
                 rctx.font_renderer.draw_text(
                     &rctx.fonts,
                     &display,
@@ -138,46 +131,20 @@ pub fn render<'a>(
             }
             if let Some(i) = set {
                 let pos = Vect::new(0., -(i as f32) / 20.);
-                let mut string = settings[i].to_string();
+                let mut string = settings[i].name.to_string();
                 string.push_str(": ");
-                match node.setting(settings[i]) {
-                    Setting::Blend(ref b) => {
-                        let bb = rctx.font_renderer
-                            .bounding_box(&rctx.fonts, "anka", size, &string)
-                            .unwrap();
-                        string.push_str(&format!("{:?}", b));
-                        let max = from_window_to_screen(dims, [bb.max.x, bb.max.y]);
-                        let pos = pos + Vect::new(max.x * 1.5, -1. / 20.);
-                        for (i, blend) in BlendType::iter_variants().enumerate() {
-                            let blend = format!("{:?}", blend);
-                            let pos = pos + Vect::new(0., -(i as f32 / 20.));
-                            rctx.font_renderer.draw_text(
-                                &rctx.fonts,
-                                &display,
-                                &mut target,
-                                "anka",
-                                size,
-                                [0., 0., 0., 1.],
-                                pos,
-                                &blend,
-                            );
-                        }
-                    }
-                    _ => {
-                        if let Some(Writing) = ctx.state {
-                            let ch = if 0 % 120 < 60 {
-                                //TODO: Fix Me
-                                '|'
-                            } else {
-                                ' '
-                            };
-                            if ctx.text.is_empty() {
-                                string.push(ch);
-                            } else {
-                                let (a, b) = ctx.text.split_at(ctx.caret);
-                                string.push_str(&format!("{}{}{}", a, ch, b));
-                            }
-                        }
+                if let Some(Writing) = ctx.state {
+                    let ch = if 0 % 120 < 60 {
+                        //TODO: Fix Me
+                        '|'
+                    } else {
+                        ' '
+                    };
+                    if ctx.text.is_empty() {
+                        string.push(ch);
+                    } else {
+                        let (a, b) = ctx.text.split_at(ctx.caret);
+                        string.push_str(&format!("{}{}{}", a, ch, b));
                     }
                 }
                 rctx.font_renderer.draw_text(
@@ -10,14 +10,18 @@ extern crate unicode_normalization;
 extern crate webweaver;
 
 use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
 
-use glium::{Display, Program};
+use glium::Display;
+use glium::texture::Texture2d;
 use glium::glutin::{ContextBuilder, EventsLoop, WindowBuilder};
 
 use daggy::NodeIndex;
 
 use texturegen::{port, Col, Generator, Port};
-use texturegen::process::{BlendType, Process, Stripes};
+use texturegen::process::{BlendType, Process, Registry, Stripes};
 use texturegen::process::Blend as BlendProcess;
 
 use State::*;
@@ -27,6 +31,7 @@ use State::*;
 // mod math;
 // mod events;
 // mod graphics;
+mod passes;
 
 pub type Vect = nalgebra::Vector2<f32>;
 pub type Mat = nalgebra::Matrix4<f32>;
@@ -62,7 +67,8 @@ impl Selection {
 
 pub struct Node {
     pos: Vect,
-    shader: RefCell<Option<Program>>,
+    /// First output of the node rendered pass by pass.
+    preview: RefCell<Option<Texture2d>>,
     inputs: RefCell<Vec<Vect>>,
     outputs: RefCell<Vec<Vect>>,
 }
@@ -71,7 +77,7 @@ impl Node {
     fn new(pos: Vect) -> Node {
         Node {
             pos: pos,
-            shader: RefCell::new(None),
+            preview: RefCell::new(None),
             inputs: RefCell::new(vec![]),
             outputs: RefCell::new(vec![]),
         }
@@ -89,6 +95,7 @@ pub struct SimContext {
     mouse_window_pos: [i32; 2],
     thingy_size: f32,
     node_width: f32,
+    registry: Registry,
 }
 
 impl SimContext {
@@ -104,6 +111,7 @@ impl SimContext {
             mouse_pos: Vect::new(0., 0.),
             node_width: 1.,
             thingy_size: 0.1,
+            registry: Registry::builtin(),
         }
     }
 }
@@ -118,8 +126,11 @@ fn main() {
 
     // let mut rctx = RenderContext::new(&display);
     let mut ctx = SimContext::new();
-    let mut gen = Generator::new();
-    construct_example_texture(&mut gen);
+    let mut gen = load_texture(GRAPH_PATH, &ctx.registry).unwrap_or_else(|| {
+        let mut gen = Generator::new();
+        construct_example_texture(&mut gen);
+        gen
+    });
     while ctx.running {
         let dims = display.get_framebuffer_dimensions();
         // rctx.cam = matrix([
@@ -140,7 +151,7 @@ fn main() {
         }
 
         // events::handle(&display, &mut events_loop, &rctx, &mut gen, &mut ctx);
-        let gen = gen.view(|source, data, process| {
+        let gen = gen.view(|schedule, data, process| {
             let half_node = ctx.node_width / 2.;
             let update = |things: &mut Vec<_>, amount, dir| {
                 things.clear();
@@ -155,12 +166,56 @@ fn main() {
             update(&mut data.inputs.borrow_mut(), process.max_in(), -1.);
             update(&mut data.outputs.borrow_mut(), process.max_out(), 1.);
 
-            let program = Program::from_source(&display, &source.vertex, &source.fragment, None)
-                .expect("Building generated shader failed");
-            *data.shader.borrow_mut() = Some(program);
+            let preview = schedule
+                .map_err(|e| e.to_string())
+                .and_then(|s| passes::render(&display, &s, PREVIEW_SIZE, PREVIEW_SIZE));
+            *data.preview.borrow_mut() = match preview {
+                Ok(texture) => Some(texture),
+                Err(e) => {
+                    println!("{}", e);
+                    None
+                }
+            };
         });
         // graphics::renderer::render(&display, &mut rctx, gen, &ctx);
     }
+    save_texture(&gen, GRAPH_PATH);
+}
+
+const GRAPH_PATH: &str = "textures/graph.ron";
+
+/// Width and height of the textures node previews are rendered to.
+const PREVIEW_SIZE: u32 = 256;
+
+fn load_texture<P: AsRef<Path>>(path: P, registry: &Registry) -> Option<Generator<Node>> {
+    let mut source = String::new();
+    File::open(path).ok()?.read_to_string(&mut source).ok()?;
+    match Generator::load(&source, registry, |pos: Option<[f32; 2]>| {
+        let pos = pos.unwrap_or([0., 0.]);
+        Node::new(Vect::new(pos[0], pos[1]))
+    }) {
+        Ok(gen) => Some(gen),
+        Err(e) => {
+            println!("{}", e);
+            None
+        }
+    }
+}
+
+fn save_texture<P: AsRef<Path>>(gen: &Generator<Node>, path: P) {
+    let path = path.as_ref();
+    let result = gen.save(|n| Some([n.pos.x, n.pos.y]));
+    match result {
+        Ok(source) => {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            if let Err(e) = File::create(path).and_then(|mut f| f.write_all(source.as_bytes())) {
+                println!("Saving texture failed: {}", e);
+            }
+        }
+        Err(e) => println!("{}", e),
+    }
 }
 
 fn construct_example_texture(gen: &mut Generator<Node>) {
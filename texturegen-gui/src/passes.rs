@@ -0,0 +1,184 @@
+//! Renders schedules of the generator pass by pass into textures.
+
+use std::collections::{HashMap, HashSet};
+
+use glium::{Display, Program, Surface, VertexBuffer};
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior,
+                      SamplerWrapFunction, UniformValue, Uniforms};
+
+use texturegen::{reduced_size, reduction, Schedule};
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+
+implement_vertex!(Vertex, position, tex_coords);
+
+/// Maps the unit square covered by the quad to the whole render target.
+const MATRIX: [[f32; 4]; 4] = [
+    [2., 0., 0., 0.],
+    [0., 2., 0., 0.],
+    [0., 0., 1., 0.],
+    [-1., -1., 0., 1.],
+];
+
+/// Uniforms of a pass, which are the textures of the earlier passes and their ranges it reads.
+struct PassUniforms<'a> {
+    textures: Vec<(&'a str, &'a Texture2d)>,
+    sampler: SamplerBehavior,
+}
+
+impl<'a> Uniforms for PassUniforms<'a> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut output: F) {
+        output("matrix", UniformValue::Mat4(MATRIX));
+        for &(name, texture) in &self.textures {
+            output(name, UniformValue::Texture2d(texture, Some(self.sampler)));
+        }
+    }
+}
+
+/// Renders every pass of the schedule, returning the texture of the output.
+pub fn render(
+    display: &Display,
+    schedule: &Schedule,
+    width: u32,
+    height: u32,
+) -> Result<Texture2d, String> {
+    let quad = [
+        Vertex {
+            position: [0., 0.],
+            tex_coords: [0., 0.],
+        },
+        Vertex {
+            position: [1., 0.],
+            tex_coords: [1., 0.],
+        },
+        Vertex {
+            position: [0., 1.],
+            tex_coords: [0., 1.],
+        },
+        Vertex {
+            position: [1., 1.],
+            tex_coords: [1., 1.],
+        },
+    ];
+    let vertices = VertexBuffer::new(display, &quad)
+        .map_err(|e| format!("Creating quad failed: {:?}", e))?;
+    let wrap = if schedule.tiling {
+        SamplerWrapFunction::Repeat
+    } else {
+        SamplerWrapFunction::Clamp
+    };
+    let sampler = SamplerBehavior {
+        wrap_function: (wrap, wrap, wrap),
+        minify_filter: MinifySamplerFilter::Linear,
+        magnify_filter: MagnifySamplerFilter::Linear,
+        ..Default::default()
+    };
+    let reduced = schedule
+        .passes
+        .iter()
+        .flat_map(|p| p.ranges.iter().map(|b| b.pass))
+        .collect::<HashSet<_>>();
+    let reduction = if reduced.is_empty() {
+        None
+    } else {
+        let source = reduction();
+        Some(
+            Program::from_source(display, &source.vertex, &source.fragment, None)
+                .map_err(|e| format!("Building reduction shader failed: {}", e))?,
+        )
+    };
+    let mut textures: Vec<Texture2d> = Vec::with_capacity(schedule.passes.len());
+    let mut ranges = HashMap::new();
+    for (index, pass) in schedule.passes.iter().enumerate() {
+        let program =
+            Program::from_source(display, &pass.source.vertex, &pass.source.fragment, None)
+                .map_err(|e| format!("Building generated shader failed: {}", e))?;
+        let texture = Texture2d::empty_with_format(
+            display,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            width,
+            height,
+        ).map_err(|e| format!("Creating pass texture failed: {:?}", e))?;
+        {
+            let uniforms = PassUniforms {
+                textures: pass.inputs
+                    .iter()
+                    .map(|b| (&b.uniform[..], &textures[b.pass]))
+                    .chain(pass.ranges.iter().map(|b| (&b.uniform[..], &ranges[&b.pass])))
+                    .collect(),
+                sampler: sampler,
+            };
+            let mut target = SimpleFrameBuffer::new(display, &texture)
+                .map_err(|e| format!("Creating pass framebuffer failed: {:?}", e))?;
+            target
+                .draw(
+                    &vertices,
+                    &NoIndices(PrimitiveType::TriangleStrip),
+                    &program,
+                    &uniforms,
+                    &Default::default(),
+                )
+                .map_err(|e| format!("Drawing pass failed: {:?}", e))?;
+        }
+        if let (true, Some(program)) = (reduced.contains(&index), reduction.as_ref()) {
+            ranges.insert(index, reduce(display, program, &vertices, &texture)?);
+        }
+        textures.push(texture);
+    }
+    textures
+        .pop()
+        .ok_or_else(|| "Schedule didn't have any passes.".into())
+}
+
+/// Reduces the texture to a 2x1 one holding its minimum on the left and maximum on the right.
+fn reduce(
+    display: &Display,
+    program: &Program,
+    vertices: &VertexBuffer<Vertex>,
+    texture: &Texture2d,
+) -> Result<Texture2d, String> {
+    let mut size = (texture.width(), texture.height());
+    let mut result: Option<Texture2d> = None;
+    loop {
+        let cells = reduced_size(size);
+        let target = Texture2d::empty_with_format(
+            display,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            cells.0 * 2,
+            cells.1,
+        ).map_err(|e| format!("Creating reduction texture failed: {:?}", e))?;
+        {
+            let uniforms = uniform! {
+                matrix: MATRIX,
+                source: result.as_ref().unwrap_or(texture),
+                size: [size.0 as i32, size.1 as i32],
+                split: result.is_some(),
+            };
+            let mut framebuffer = SimpleFrameBuffer::new(display, &target)
+                .map_err(|e| format!("Creating reduction framebuffer failed: {:?}", e))?;
+            framebuffer
+                .draw(
+                    vertices,
+                    &NoIndices(PrimitiveType::TriangleStrip),
+                    program,
+                    &uniforms,
+                    &Default::default(),
+                )
+                .map_err(|e| format!("Drawing reduction failed: {:?}", e))?;
+        }
+        result = Some(target);
+        if cells == (1, 1) {
+            return Ok(result.unwrap());
+        }
+        size = cells;
+    }
+}
@@ -0,0 +1,846 @@
+use std::collections::HashMap;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use Col;
+use Node;
+use dag::{Port, PortNumbered};
+use pass::{self, Target};
+
+pub use self::prelude::*;
+
+mod prelude;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub fn new(x: f32, y: f32) -> Vec2 {
+        Vec2 { x: x, y: y }
+    }
+
+    pub fn splat(v: f32) -> Vec2 {
+        Vec2::new(v, v)
+    }
+
+    pub fn map<F: FnMut(f32) -> f32>(self, mut fun: F) -> Vec2 {
+        Vec2::new(fun(self.x), fun(self.y))
+    }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec4 {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Vec4 {
+    pub fn new(r: f32, g: f32, b: f32, a: f32) -> Vec4 {
+        Vec4 {
+            r: r,
+            g: g,
+            b: b,
+            a: a,
+        }
+    }
+
+    pub fn splat(v: f32) -> Vec4 {
+        Vec4::new(v, v, v, v)
+    }
+
+    /// Grayscale value with opaque alpha, as the noise snippets emit it.
+    pub fn gray(v: f32) -> Vec4 {
+        Vec4::new(v, v, v, 1.)
+    }
+
+    pub fn map<F: FnMut(f32) -> f32>(self, mut fun: F) -> Vec4 {
+        Vec4::new(fun(self.r), fun(self.g), fun(self.b), fun(self.a))
+    }
+
+    pub fn zip<F: FnMut(f32, f32) -> f32>(self, other: Vec4, mut fun: F) -> Vec4 {
+        Vec4::new(
+            fun(self.r, other.r),
+            fun(self.g, other.g),
+            fun(self.b, other.b),
+            fun(self.a, other.a),
+        )
+    }
+}
+
+impl From<Col> for Vec4 {
+    fn from(c: Col) -> Vec4 {
+        Vec4::new(c.red, c.green, c.blue, c.alpha)
+    }
+}
+
+impl From<Vec4> for Col {
+    fn from(v: Vec4) -> Col {
+        Col::new(v.r, v.g, v.b, v.a)
+    }
+}
+
+macro_rules! impl_ops {
+    ($t:ident { $($f:ident),* }) => {
+        impl Add for $t {
+            type Output = $t;
+            fn add(self, o: $t) -> $t {
+                $t { $($f: self.$f + o.$f),* }
+            }
+        }
+
+        impl Sub for $t {
+            type Output = $t;
+            fn sub(self, o: $t) -> $t {
+                $t { $($f: self.$f - o.$f),* }
+            }
+        }
+
+        impl Mul for $t {
+            type Output = $t;
+            fn mul(self, o: $t) -> $t {
+                $t { $($f: self.$f * o.$f),* }
+            }
+        }
+
+        impl Div for $t {
+            type Output = $t;
+            fn div(self, o: $t) -> $t {
+                $t { $($f: self.$f / o.$f),* }
+            }
+        }
+
+        impl Mul<f32> for $t {
+            type Output = $t;
+            fn mul(self, o: f32) -> $t {
+                $t { $($f: self.$f * o),* }
+            }
+        }
+
+        impl Div<f32> for $t {
+            type Output = $t;
+            fn div(self, o: f32) -> $t {
+                $t { $($f: self.$f / o),* }
+            }
+        }
+
+        impl Neg for $t {
+            type Output = $t;
+            fn neg(self) -> $t {
+                $t { $($f: -self.$f),* }
+            }
+        }
+    }
+}
+
+impl_ops!(Vec2 { x, y });
+impl_ops!(Vec4 { r, g, b, a });
+
+/// Changes of the coordinates to the horizontal and vertical neighbours in the same 2x2 block
+/// of pixels, which is how GPUs find derivatives.
+type Derivatives = (Vec2, Vec2);
+
+/// What a process sees of the graph while it is being evaluated on the CPU.
+///
+/// This is the counterpart of `Context` for the shader path: instead of naming
+/// variables it hands out the values of the inputs at the pixel being computed.
+pub struct Sampler<'a> {
+    coords: Vec2,
+    derivatives: Derivatives,
+    texel: Vec2,
+    inputs: &'a [u32],
+    fetch: &'a Fn(u32, Vec2, Derivatives) -> Option<Vec4>,
+    range: &'a Fn(u32) -> Option<(Vec4, Vec4)>,
+    previous: Option<&'a Texture>,
+    stage: u32,
+    tiling: bool,
+}
+
+impl<'a> Sampler<'a> {
+    /// Texture coordinates being evaluated, matching `Context::coords` in the shader.
+    pub fn coords(&self) -> Vec2 {
+        self.coords
+    }
+
+    /// Size of a single pixel in texture coordinates.
+    pub fn texel(&self) -> Vec2 {
+        self.texel
+    }
+
+    /// How much the coordinates change to the neighbouring pixels, matching `fwidth` of
+    /// `Context::coords` in the shader.
+    ///
+    /// This is the size of a pixel unless a parent moves the coordinates with `input_mapped`.
+    pub fn fwidth(&self) -> Vec2 {
+        let (dx, dy) = self.derivatives;
+        dx.map(f32::abs) + dy.map(f32::abs)
+    }
+
+    /// Whether the output has to wrap seamlessly at the texture edges.
+    pub fn tiling(&self) -> bool {
+        self.tiling
+    }
+
+    /// Stage of the process being evaluated, matching `Context::stage`.
+    pub fn stage(&self) -> u32 {
+        self.stage
+    }
+
+    /// Result of the previous stage at given coordinates, matching `Context::previous_at`.
+    pub fn previous_at(&self, coords: Vec2) -> Option<Vec4> {
+        self.previous.map(|t| t.sample(coords, self.tiling))
+    }
+
+    pub fn input(&self, index: u32) -> Option<Vec4> {
+        self.input_at(index, self.coords)
+    }
+
+    /// Evaluates the input at other coordinates, which change along with the ones evaluated.
+    pub fn input_at(&self, index: u32, coords: Vec2) -> Option<Vec4> {
+        (self.fetch)(index, coords, self.derivatives)
+    }
+
+    /// Evaluates the input at coordinates mapped by a function of them, like `Context::input_at`
+    /// with an expression of `Context::coords`.
+    ///
+    /// The derivatives the input sees are those of the mapping, so that antialiasing matches
+    /// what `fwidth` gives in the shader.
+    pub fn input_mapped(&self, index: u32, map: &Fn(Vec2) -> Vec2) -> Option<Vec4> {
+        let (dx, dy) = self.derivatives;
+        let coords = map(self.coords);
+        let derivatives = (map(self.coords + dx) - coords, map(self.coords + dy) - coords);
+        (self.fetch)(index, coords, derivatives)
+    }
+
+    /// Minimum and maximum of the input over the whole texture, matching `Context::input_range`.
+    pub fn input_range(&self, index: u32) -> Option<(Vec4, Vec4)> {
+        (self.range)(index)
+    }
+
+    pub fn first_input(&self) -> Option<Vec4> {
+        self.inputs.first().and_then(|i| self.input(*i))
+    }
+
+    pub fn input_len(&self) -> usize {
+        self.inputs.len()
+    }
+}
+
+/// Image produced by the CPU evaluator.
+///
+/// Rows are stored from top to bottom, which is the same order the exported
+/// PNG files have.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    width: u32,
+    height: u32,
+    pixels: Vec<Col>,
+}
+
+impl Texture {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Col] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<Col> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Bilinearly filtered color at texture coordinates, which are either
+    /// repeated or clamped to the edge.
+    pub fn sample(&self, coords: Vec2, repeat: bool) -> Vec4 {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let x = coords.x * width - 0.5;
+        let y = (1. - coords.y) * height - 0.5;
+        let (fx, fy) = (fract(x), fract(y));
+        let texel = |dx: f32, dy: f32| -> Vec4 {
+            let (x, y) = if repeat {
+                (modulo(x.floor() + dx, width), modulo(y.floor() + dy, height))
+            } else {
+                (
+                    clamp(x.floor() + dx, 0., width - 1.),
+                    clamp(y.floor() + dy, 0., height - 1.),
+                )
+            };
+            let (x, y) = (x as u32, y as u32);
+            self.pixels[(y * self.width + x) as usize].into()
+        };
+        let top = texel(0., 0.) * (1. - fx) + texel(1., 0.) * fx;
+        let bottom = texel(0., 1.) * (1. - fx) + texel(1., 1.) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    /// Minimum and maximum of each channel over all pixels.
+    ///
+    /// Empty textures have the full range from 0 to 1, so that remapping by it does nothing.
+    pub fn range(&self) -> (Vec4, Vec4) {
+        if self.pixels.is_empty() {
+            return (Vec4::splat(0.), Vec4::splat(1.));
+        }
+        let first = Vec4::from(self.pixels[0]);
+        self.pixels.iter().fold((first, first), |(min, max), &c| {
+            let v = Vec4::from(c);
+            (min.zip(v, f32::min), max.zip(v, f32::max))
+        })
+    }
+
+    /// Packs pixels to 8-bit sRGBA, same as reading back a `SrgbTexture2d`.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.pixels.len() * 4);
+        for c in &self.pixels {
+            for v in &[c.red, c.green, c.blue, c.alpha] {
+                result.push((clamp(*v, 0., 1.) * 255.).round() as u8);
+            }
+        }
+        result
+    }
+}
+
+/// Renders the output, rendering the pass boundaries to textures first like the schedule does.
+pub fn render<T>(
+    dag: &PortNumbered<Node<T>>,
+    output: Port<u32>,
+    width: u32,
+    height: u32,
+    tiling: bool,
+) -> Texture {
+    let boundaries = pass::boundaries(dag, output.node);
+    let mut textures = HashMap::new();
+    let mut ranges = HashMap::new();
+    for target in pass::order(dag, output, &boundaries) {
+        let texture = render_pass(dag, target, width, height, tiling, &textures, &ranges);
+        if let Target::Port(p) = target {
+            ranges.insert(p, texture.range());
+        }
+        textures.insert(target, texture);
+    }
+    textures.remove(&Target::Port(output)).unwrap()
+}
+
+fn render_pass<T>(
+    dag: &PortNumbered<Node<T>>,
+    target: Target,
+    width: u32,
+    height: u32,
+    tiling: bool,
+    textures: &HashMap<Target, Texture>,
+    ranges: &HashMap<Port<u32>, (Vec4, Vec4)>,
+) -> Texture {
+    let texel = Vec2::new(1. / width as f32, 1. / height as f32);
+    let rendering = Rendering {
+        texel: texel,
+        tiling: tiling,
+        textures: textures,
+        ranges: ranges,
+    };
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let coords = Vec2::new(
+                (x as f32 + 0.5) * texel.x,
+                1. - (y as f32 + 0.5) * texel.y,
+            );
+            let dx = if x % 2 == 0 { texel.x } else { -texel.x };
+            let dy = if y % 2 == 0 { -texel.y } else { texel.y };
+            let derivatives = (Vec2::new(dx, 0.), Vec2::new(0., dy));
+            pixels.push(evaluate(dag, target, coords, derivatives, &rendering).into());
+        }
+    }
+    Texture {
+        width: width,
+        height: height,
+        pixels: pixels,
+    }
+}
+
+/// What is the same for all pixels of a pass: its resolution, tiling and the results of the
+/// earlier passes.
+struct Rendering<'a> {
+    texel: Vec2,
+    tiling: bool,
+    textures: &'a HashMap<Target, Texture>,
+    ranges: &'a HashMap<Port<u32>, (Vec4, Vec4)>,
+}
+
+fn evaluate<T>(
+    dag: &PortNumbered<Node<T>>,
+    target: Target,
+    coords: Vec2,
+    derivatives: Derivatives,
+    rendering: &Rendering,
+) -> Vec4 {
+    let Rendering {
+        texel,
+        tiling,
+        textures,
+        ranges,
+    } = *rendering;
+    let node = target.node();
+    let process = &dag.node_weight(node)
+        .expect("Node or it's parent didn't exist.")
+        .process;
+    let stage = pass::stage_of(dag, node, target);
+    let parents = dag.parents(node).collect::<Vec<_>>();
+    let inputs = parents.iter().map(|p| p.1).collect::<Vec<_>>();
+    let fetch = |index, coords, derivatives| {
+        parents
+            .iter()
+            .find(|p| p.1 == index)
+            .map(|p| match textures.get(&Target::Port(p.0)) {
+                Some(texture) => texture.sample(coords, tiling),
+                None => evaluate(dag, Target::Port(p.0), coords, derivatives, rendering),
+            })
+    };
+    let range = |index| {
+        parents.iter().find(|p| p.1 == index).map(|p| {
+            ranges
+                .get(&p.0)
+                .cloned()
+                .unwrap_or((Vec4::splat(0.), Vec4::splat(1.)))
+        })
+    };
+    let sampler = Sampler {
+        coords: coords,
+        derivatives: derivatives,
+        texel: texel,
+        inputs: &inputs,
+        fetch: &fetch,
+        range: &range,
+        previous: stage
+            .checked_sub(1)
+            .and_then(|s| textures.get(&Target::Stage(node, s))),
+        stage: stage,
+        tiling: tiling,
+    };
+    let output = match target {
+        Target::Port(p) => p.port,
+        Target::Stage(..) => 0,
+    };
+    process.evaluate(&sampler, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Generator;
+    use daggy::NodeIndex;
+    use dag::port;
+    use process::{AmbientOcclusion, Blend, BlendType, Blur, BlurType, Channel, ColorModel,
+                  CombineChannels, Constant, Curvature, Derivative, EdgeDetect, EdgeDetectType,
+                  FractalNoise, FractalType, Gradient, GradientType, HueSaturation, Invert, Noise,
+                  NormalMap, Process, Select, Shape, ShapeType, SplitChannels, Stop, Stripes,
+                  Transform, Value, VoronoiNoise, Warp, WarpType};
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    fn color(c: [u8; 4]) -> Col {
+        Col::new(
+            c[0] as f32 / 255.,
+            c[1] as f32 / 255.,
+            c[2] as f32 / 255.,
+            c[3] as f32 / 255.,
+        )
+    }
+
+    fn stripes(ver: u32) -> Box<Process> {
+        Stripes::new(ver, 1, color(WHITE), color(BLACK))
+    }
+
+    /// Renders the first output of the node and returns its pixels from the top left.
+    fn pixels(g: &Generator<()>, node: NodeIndex, width: u32, height: u32) -> Vec<[u8; 4]> {
+        render(&g.dag, port(node, 0), width, height, g.tiling())
+            .to_rgba8()
+            .chunks(4)
+            .map(|c| [c[0], c[1], c[2], c[3]])
+            .collect()
+    }
+
+    #[test]
+    fn constant() {
+        let mut g = Generator::new();
+        let c = g.add(Constant::new(color(RED)), ());
+        assert_eq!(pixels(&g, c, 3, 2), vec![RED; 6]);
+    }
+
+    #[test]
+    fn evaluate_missing_output() {
+        let mut g = Generator::new();
+        let c = g.add(Constant::new(color(RED)), ());
+        assert!(g.evaluate(port(c, 0), 1, 1).is_some());
+        assert!(g.evaluate(port(c, 1), 1, 1).is_none());
+    }
+
+    #[test]
+    fn stripes_alternate() {
+        let mut g = Generator::new();
+        let s = g.add(stripes(4), ());
+        let row = vec![WHITE, WHITE, BLACK, BLACK, WHITE, WHITE, BLACK, BLACK];
+        assert_eq!(pixels(&g, s, 8, 1), row);
+    }
+
+    #[test]
+    fn stripes_tiling_rounds_up_to_even_count() {
+        let mut g = Generator::new();
+        let s = g.add(stripes(3), ());
+        assert_eq!(pixels(&g, s, 6, 1), vec![WHITE, WHITE, BLACK, BLACK, WHITE, WHITE]);
+        // The single horizontal stripe already wraps, so the rows stay the same.
+        g.set_tiling(true);
+        let row = [WHITE, WHITE, BLACK, BLACK, WHITE, WHITE, BLACK, BLACK];
+        let expected = row.iter().chain(row.iter()).cloned().collect::<Vec<_>>();
+        assert_eq!(pixels(&g, s, 8, 2), expected);
+    }
+
+    /// Largest difference between pixels next to each other inside the texture, and across its
+    /// left and right or top and bottom edges.
+    fn steps(texture: &Texture) -> (f32, f32) {
+        let (width, height) = (texture.width(), texture.height());
+        let value = |x: u32, y: u32| Vec4::from(texture.get(x % width, y % height).unwrap());
+        let step = |a: Vec4, b: Vec4| {
+            let d = (a - b).map(f32::abs);
+            d.r.max(d.g).max(d.b).max(d.a)
+        };
+        let (mut inside, mut edges) = (0f32, 0f32);
+        for y in 0..height {
+            for x in 0..width {
+                let right = step(value(x, y), value(x + 1, y));
+                let below = step(value(x, y), value(x, y + 1));
+                inside = inside.max(if x + 1 < width { right } else { 0. });
+                inside = inside.max(if y + 1 < height { below } else { 0. });
+                edges = edges.max(if x + 1 == width { right } else { 0. });
+                edges = edges.max(if y + 1 == height { below } else { 0. });
+            }
+        }
+        (inside, edges)
+    }
+
+    #[test]
+    fn tiling_wraps_at_edges() {
+        let processes = vec![
+            stripes(3),
+            Noise::new(1, 3, 5),
+            VoronoiNoise::new(2, 3, 5, 1., 0.),
+            FractalNoise::new(3, 3, 2, 4, 2.2, 0.5, FractalType::Fbm),
+        ];
+        for process in processes {
+            let name = process.name();
+            let mut g = Generator::new();
+            let n = g.add(process, ());
+            g.set_tiling(true);
+            let (inside, edges) = steps(&render(&g.dag, port(n, 0), 64, 48, true));
+            assert!(edges <= inside * 1.5, "{}: {} at edges, {} inside", name, edges, inside);
+        }
+    }
+
+    #[test]
+    fn noise_tiling_only_changes_edges() {
+        let mut g = Generator::new();
+        let n = g.add(Noise::new(1, 8, 8), ());
+        let (inside, edges) = steps(&render(&g.dag, port(n, 0), 64, 64, false));
+        assert!(edges > inside * 2., "{} at edges, {} inside", edges, inside);
+        let untiled = pixels(&g, n, 64, 64);
+        g.set_tiling(true);
+        let tiled = pixels(&g, n, 64, 64);
+        assert!(tiled != untiled);
+        // Corners of the simplices are at most two lattice cells away.
+        for y in 16..48 {
+            for x in 16..48 {
+                let i = y * 64 + x;
+                assert_eq!(tiled[i], untiled[i], "{}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_depends_only_on_seed() {
+        let noises: Vec<fn(u32) -> Box<Process>> = vec![
+            |seed| Noise::new(seed, 4, 4),
+            |seed| VoronoiNoise::new(seed, 4, 4, 1., 0.5),
+            |seed| FractalNoise::new(seed, 2, 2, 4, 2., 0.5, FractalType::Fbm),
+        ];
+        for noise in noises {
+            let rendered = [0, 0, 1]
+                .iter()
+                .map(|&seed| {
+                    let mut g = Generator::new();
+                    let n = g.add(noise(seed), ());
+                    pixels(&g, n, 32, 32)
+                })
+                .collect::<Vec<_>>();
+            let name = noise(0).name();
+            assert!(rendered[0] == rendered[1], "{} changed with the same seed", name);
+            assert!(rendered[0] != rendered[2], "{} didn't change with the seed", name);
+        }
+    }
+
+    #[test]
+    fn range_of_empty_texture() {
+        let mut g = Generator::new();
+        let s = g.add(stripes(2), ());
+        let texture = render(&g.dag, port(s, 0), 0, 0, false);
+        assert_eq!(texture.range(), (Vec4::splat(0.), Vec4::splat(1.)));
+        let texture = render(&g.dag, port(s, 0), 4, 1, false);
+        assert_eq!(texture.range(), (Vec4::new(0., 0., 0., 1.), Vec4::splat(1.)));
+    }
+
+    #[test]
+    fn invert() {
+        let mut g = Generator::new();
+        let s = g.add(stripes(2), ());
+        let i = g.add(Invert::new(), ());
+        g.connect(port(s, 0), port(i, 0));
+        assert_eq!(pixels(&g, i, 4, 1), vec![BLACK, BLACK, WHITE, WHITE]);
+    }
+
+    #[test]
+    fn blend_multiply() {
+        let mut g = Generator::new();
+        let s = g.add(stripes(2), ());
+        let c = g.add(Constant::new(color(RED)), ());
+        let b = g.add(Blend::new(BlendType::Multiply, BlendType::Normal), ());
+        g.connect(port(c, 0), port(b, 0));
+        g.connect(port(s, 0), port(b, 1));
+        assert_eq!(pixels(&g, b, 4, 1), vec![RED, RED, BLACK, BLACK]);
+    }
+
+    #[test]
+    fn select() {
+        let mut g = Generator::new();
+        let s = g.add(stripes(2), ());
+        let red = g.add(Constant::new(color(RED)), ());
+        let blue = g.add(Constant::new(color(BLUE)), ());
+        let select = g.add(Select::new(0.5), ());
+        g.connect(port(red, 0), port(select, 0));
+        g.connect(port(s, 0), port(select, 1));
+        g.connect(port(blue, 0), port(select, 2));
+        assert_eq!(pixels(&g, select, 4, 1), vec![BLUE, BLUE, RED, RED]);
+    }
+
+    #[test]
+    fn edge_detect_finds_step() {
+        for &edtype in &[EdgeDetectType::Sobel, EdgeDetectType::FreiChen] {
+            let mut g = Generator::new();
+            let s = g.add(stripes(2), ());
+            // Frei-Chen gives half on the bright side of the step, as flat white adds to it.
+            let edges = g.add(EdgeDetect::new(0.4, 1. / 8., edtype), ());
+            g.connect(port(s, 0), port(edges, 0));
+            let mut row = vec![BLACK; 8];
+            row[3] = WHITE;
+            row[4] = WHITE;
+            assert_eq!(pixels(&g, edges, 8, 1), row, "{:?}", edtype);
+        }
+    }
+
+    #[test]
+    fn warp_moves_by_intensity() {
+        for &wtype in &[WarpType::Directional, WarpType::Vector] {
+            let mut g = Generator::new();
+            let s = g.add(stripes(4), ());
+            let d = g.add(Constant::new(color([255, 255, 0, 255])), ());
+            let w = g.add(Warp::new(0.25, 0., wtype), ());
+            g.connect(port(s, 0), port(w, 0));
+            g.connect(port(d, 0), port(w, 1));
+            let row = pixels(&g, w, 8, 1);
+            assert_eq!(row[..6], [BLACK, BLACK, WHITE, WHITE, BLACK, BLACK], "{:?}", wtype);
+        }
+    }
+
+    #[test]
+    fn transformed_shape_has_the_same_edges() {
+        let mut g = Generator::new();
+        let mut small = Shape::new(ShapeType::Circle);
+        small.set("width", Value::Float(0.125)).unwrap();
+        small.set("height", Value::Float(0.125)).unwrap();
+        let small = g.add(small, ());
+        let shape = g.add(Shape::new(ShapeType::Circle), ());
+        let mut transform = Transform::new();
+        transform.set("scale x", Value::Float(0.25)).unwrap();
+        transform.set("scale y", Value::Float(0.25)).unwrap();
+        let transform = g.add(transform, ());
+        g.connect(port(shape, 0), port(transform, 0));
+        let expected = render(&g.dag, port(small, 0), 32, 32, false);
+        assert!(expected.pixels().iter().any(|c| c.red > 0.1 && c.red < 0.9));
+        let transformed = render(&g.dag, port(transform, 0), 32, 32, false);
+        for (a, b) in transformed.pixels().iter().zip(expected.pixels()) {
+            assert!((a.red - b.red).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn box_blur_averages_neighbours() {
+        let mut g = Generator::new();
+        let s = g.add(stripes(4), ());
+        let b = g.add(Blur::new(0.125, 0., BlurType::Box), ());
+        g.connect(port(s, 0), port(b, 0));
+        let texture = render(&g.dag, port(b, 0), 8, 2, false);
+        let row = [1., 2. / 3., 1. / 3., 1. / 3., 2. / 3., 2. / 3., 1. / 3., 0.];
+        for (i, c) in texture.pixels().iter().enumerate() {
+            assert!((c.red - row[i % 8]).abs() < 1e-4, "{}: {:?}", i, c);
+        }
+    }
+
+    #[test]
+    fn gaussian_blur_matches_two_dimensional_kernel() {
+        let mut g = Generator::new();
+        let n = g.add(Noise::new(3, 4, 4), ());
+        let b = g.add(Blur::new(0.125, 0., BlurType::Gaussian), ());
+        g.connect(port(n, 0), port(b, 0));
+        let noise = render(&g.dag, port(n, 0), 32, 32, true);
+        let blurred = render(&g.dag, port(b, 0), 32, 32, true);
+        // Radius of 4 pixels puts the samples on pixel centers, 4 on each side.
+        let weight = |i: i32| (-(i * i) as f32 * 4.5 / 16.).exp();
+        for y in 0..32 {
+            for x in 0..32 {
+                let (mut sum, mut total) = (0., 0.);
+                for j in -4..5 {
+                    for i in -4..5 {
+                        let c = noise.get((x + i + 32) as u32 % 32, (y + j + 32) as u32 % 32);
+                        sum += c.unwrap().red * weight(i) * weight(j);
+                        total += weight(i) * weight(j);
+                    }
+                }
+                let c = blurred.get(x as u32, y as u32).unwrap();
+                assert!((c.red - sum / total).abs() < 1e-4, "{}, {}", x, y);
+            }
+        }
+    }
+
+    /// Linear gradient from black to white along the angle.
+    fn ramp(angle: f32) -> Box<Process> {
+        let stops = vec![Stop::new(0., color(BLACK)), Stop::new(1., color(WHITE))];
+        Gradient::new(GradientType::Linear, angle, stops)
+    }
+
+    /// Renders the output of `process` at 16x16 with `input` connected to its first input.
+    fn apply(input: Box<Process>, process: Box<Process>) -> Texture {
+        let mut g = Generator::new();
+        let i = g.add(input, ());
+        let p = g.add(process, ());
+        g.connect(port(i, 0), port(p, 0));
+        render(&g.dag, port(p, 0), 16, 16, false)
+    }
+
+    fn center(input: Box<Process>, process: Box<Process>) -> Vec4 {
+        Vec4::from(apply(input, process).get(8, 8).unwrap())
+    }
+
+    fn near(a: Vec4, b: Vec4) -> bool {
+        let d = (a - b).map(f32::abs);
+        d.r.max(d.g).max(d.b).max(d.a) < 1e-4
+    }
+
+    #[test]
+    fn normal_map_of_flat_height_points_up() {
+        for &directx in &[false, true] {
+            for &derivative in &[Derivative::Sobel, Derivative::CentralDifference] {
+                let normals = NormalMap::new(1., 1. / 16., directx, derivative);
+                let c = center(Constant::new(color([128, 128, 128, 255])), normals);
+                assert!(near(c, Vec4::new(0.5, 0.5, 1., 1.)), "{:?}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn normal_map_of_ramp_tilts_away_from_it() {
+        let normals = |directx| NormalMap::new(1., 1. / 16., directx, Derivative::Sobel);
+        // Height rising to the right tilts the normal to the left in both conventions.
+        let (gl, dx) = (center(ramp(0.), normals(false)), center(ramp(0.), normals(true)));
+        assert!(gl.r < 0.45 && gl.b < 1., "{:?}", gl);
+        assert!(near(gl, dx), "{:?} and {:?}", gl, dx);
+        assert!((gl.g - 0.5).abs() < 1e-4, "{:?}", gl);
+        // Height rising upwards tilts it down in OpenGL and up in DirectX.
+        let (gl, dx) = (center(ramp(90.), normals(false)), center(ramp(90.), normals(true)));
+        assert!((gl.r - 0.5).abs() < 1e-4, "{:?}", gl);
+        assert!(gl.g < 0.45, "{:?}", gl);
+        assert!((gl.g + dx.g - 1.).abs() < 1e-4, "{:?} and {:?}", gl, dx);
+        assert!((gl.b - dx.b).abs() < 1e-4, "{:?} and {:?}", gl, dx);
+    }
+
+    #[test]
+    fn planes_are_neither_occluded_nor_curved() {
+        for &angle in &[0., 30., 90.] {
+            let occlusion = center(ramp(angle), AmbientOcclusion::new(0.125, 8, 1.));
+            assert!(near(occlusion, Vec4::gray(1.)), "{:?} at {}", occlusion, angle);
+            let curvature = center(ramp(angle), Curvature::new(1. / 16., 4.));
+            assert!(near(curvature, Vec4::gray(0.5)), "{:?} at {}", curvature, angle);
+        }
+    }
+
+    #[test]
+    fn foot_of_step_is_occluded_and_concave() {
+        // White on the left half is higher than the black on the right.
+        let at = |process: Box<Process>, x: u32| {
+            let texture = apply(stripes(2), process);
+            Vec4::from(texture.get(x, 8).unwrap()).r
+        };
+        let occlusion = |x| at(AmbientOcclusion::new(0.125, 8, 1.), x);
+        let curvature = |x| at(Curvature::new(1. / 16., 1.), x);
+        assert!(occlusion(8) < 0.9, "{}", occlusion(8));
+        assert!((occlusion(7) - 1.).abs() < 1e-4, "{}", occlusion(7));
+        assert!(curvature(8) < 0.45, "{}", curvature(8));
+        assert!(curvature(7) > 0.55, "{}", curvature(7));
+    }
+
+    /// Diagonal gradient between two colors differing in every channel.
+    fn colors() -> Box<Process> {
+        let stops = vec![
+            Stop::new(0., Col::new(0.9, 0.1, 0.3, 0.2)),
+            Stop::new(1., Col::new(0.2, 0.6, 1., 1.)),
+        ];
+        Gradient::new(GradientType::Linear, 45., stops)
+    }
+
+    fn same(a: &Texture, b: &Texture) -> bool {
+        a.pixels().iter().zip(b.pixels()).all(|(&a, &b)| near(a.into(), b.into()))
+    }
+
+    #[test]
+    fn split_and_combine_channels() {
+        let mut g = Generator::new();
+        let c = g.add(colors(), ());
+        let split = g.add(SplitChannels::new(), ());
+        let red = Channel::Red;
+        let combine = g.add(CombineChannels::new(red, red, red, red), ());
+        g.connect(port(c, 0), port(split, 0));
+        for i in 0..4 {
+            g.connect(port(split, i), port(combine, i));
+        }
+        let input = render(&g.dag, port(c, 0), 16, 16, false);
+        assert!(same(&render(&g.dag, port(combine, 0), 16, 16, false), &input));
+    }
+
+    #[test]
+    fn neutral_hue_saturation() {
+        for &model in &[ColorModel::Hsv, ColorModel::Hsl] {
+            let adjusted = apply(colors(), HueSaturation::new(model, 0., 0., 0.));
+            let mut g = Generator::new();
+            let c = g.add(colors(), ());
+            assert!(same(&adjusted, &render(&g.dag, port(c, 0), 16, 16, false)), "{:?}", model);
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Rust ports of the GLSL builtins and prelude functions used by the shader snippets.
+//!
+//! These are kept as close to their GLSL counterparts as possible so that the CPU
+//! evaluator produces the same values as the generated shaders.
+
+use std::f32::consts;
+
+use super::Vec2;
+
+pub fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+/// GLSL `mod`, which unlike `%` always has the sign of `y`.
+pub fn modulo(x: f32, y: f32) -> f32 {
+    x - y * (x / y).floor()
+}
+
+pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
+    x.max(min).min(max)
+}
+
+pub fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1. - t) + b * t
+}
+
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+fn pcg3d(v: [u32; 3]) -> [u32; 3] {
+    fn mix(v: &mut [u32; 3]) {
+        v[0] = v[0].wrapping_add(v[1].wrapping_mul(v[2]));
+        v[1] = v[1].wrapping_add(v[2].wrapping_mul(v[0]));
+        v[2] = v[2].wrapping_add(v[0].wrapping_mul(v[1]));
+    }
+    let mut v = [
+        v[0].wrapping_mul(1664525).wrapping_add(1013904223),
+        v[1].wrapping_mul(1664525).wrapping_add(1013904223),
+        v[2].wrapping_mul(1664525).wrapping_add(1013904223),
+    ];
+    mix(&mut v);
+    for x in &mut v {
+        *x ^= *x >> 16;
+    }
+    mix(&mut v);
+    v
+}
+
+/// Random values in [0, 1) for integer point `p`, exactly the same as in the shader.
+pub fn hash3(p: Vec2, seed: u32) -> [f32; 3] {
+    let h = pcg3d([p.x as i32 as u32, p.y as i32 as u32, seed]);
+    [
+        (h[0] >> 8) as f32 / 16777216.,
+        (h[1] >> 8) as f32 / 16777216.,
+        (h[2] >> 8) as f32 / 16777216.,
+    ]
+}
+
+pub fn iqnoise(x: Vec2, u: f32, v: f32, seed: u32) -> f32 {
+    let floor = x.map(f32::floor);
+    let fract = x - floor;
+
+    let k = 1. + 63. * (1. - v).powi(4);
+
+    let mut va = 0.;
+    let mut wt = 0.;
+    for x in -2..3 {
+        for y in -2..3 {
+            let offset = Vec2::new(x as f32, y as f32);
+            let o = hash3(floor + offset, seed);
+            let r = offset - fract + Vec2::new(o[0] * u, o[1] * u);
+            let d = r.dot(r);
+            let ww = (1. - smoothstep(0., 1.414, d.sqrt())).powf(k);
+            va += o[2] * ww;
+            wt += ww;
+        }
+    }
+    va / wt
+}
+
+pub fn iqnoise_tiled(x: Vec2, u: f32, v: f32, period: Vec2, seed: u32) -> f32 {
+    let floor = x.map(f32::floor);
+    let fract = x - floor;
+
+    let k = 1. + 63. * (1. - v).powi(4);
+
+    let mut va = 0.;
+    let mut wt = 0.;
+    for x in -2..3 {
+        for y in -2..3 {
+            let offset = Vec2::new(x as f32, y as f32);
+            let cell = floor + offset;
+            let o = hash3(
+                Vec2::new(modulo(cell.x, period.x), modulo(cell.y, period.y)),
+                seed,
+            );
+            let r = offset - fract + Vec2::new(o[0] * u, o[1] * u);
+            let d = r.dot(r);
+            let ww = (1. - smoothstep(0., 1.414, d.sqrt())).powf(k);
+            va += o[2] * ww;
+            wt += ww;
+        }
+    }
+    va / wt
+}
+
+fn mod289(x: f32) -> f32 {
+    x - (x * (1. / 289.)).floor() * 289.
+}
+
+fn permute(x: f32) -> f32 {
+    mod289(((x * 34.) + 1.) * x)
+}
+
+/// Offset of the lattice points hashed for different seeds.
+fn seed_offset(seed: u32) -> Vec2 {
+    let h = hash3(Vec2::splat(0.), seed);
+    (Vec2::new(h[0], h[1]) * 289.).map(f32::floor)
+}
+
+/// Simplex noise repeating every `period` units, components of which are zero when not tiling.
+pub fn snoise(seed: u32, x: Vec2, period: Vec2) -> f32 {
+    // Different seeds hash lattice points as if they were elsewhere, which keeps the period
+    let offset = seed_offset(seed);
+    // Transform to the grid where simplices are halves of unit squares
+    let uv = Vec2::new(x.x + x.y * 0.5, x.y);
+    let i0 = uv.map(f32::floor);
+    let f0 = uv - i0;
+    let o1 = if f0.x >= f0.y {
+        Vec2::new(1., 0.)
+    } else {
+        Vec2::new(0., 1.)
+    };
+
+    // Corners back in texture space
+    let v0 = Vec2::new(i0.x - i0.y * 0.5, i0.y);
+    let corners = [
+        v0,
+        Vec2::new(v0.x + o1.x - o1.y * 0.5, v0.y + o1.y),
+        Vec2::new(v0.x + 0.5, v0.y + 1.),
+    ];
+
+    let mut result = 0.;
+    for v in &corners {
+        // Wrap corner to the period and round back to the grid
+        let w = wrap(*v, period);
+        let iu = (w.x + 0.5 * w.y + 0.5).floor();
+        let iv = (w.y + 0.5).floor();
+
+        let p = permute(permute(mod289(iv + offset.y)) + mod289(iu + offset.x));
+        let psi = p * 0.07482;
+        let g = Vec2::new(psi.cos(), psi.sin());
+
+        let d = x - *v;
+        let w = (0.8 - d.dot(d)).max(0.);
+        let w = w * w;
+        let w = w * w;
+        result += w * g.dot(d);
+    }
+    10.9 * result * 0.5 + 0.5
+}
+
+/// Lattice point wrapped to the period, components of which are zero when not tiling.
+pub fn wrap(p: Vec2, period: Vec2) -> Vec2 {
+    Vec2::new(
+        if period.x > 0. {
+            modulo(p.x, period.x)
+        } else {
+            p.x
+        },
+        if period.y > 0. {
+            modulo(p.y, period.y)
+        } else {
+            p.y
+        },
+    )
+}
+
+fn fade(t: Vec2) -> Vec2 {
+    t.map(|t| t * t * t * (t * (t * 6. - 15.) + 10.))
+}
+
+fn perlin_corner(i: Vec2, f: Vec2, corner: Vec2, period: Vec2, seed: u32) -> f32 {
+    let a = hash3(wrap(i + corner, period), seed)[0] * 2. * consts::PI;
+    Vec2::new(a.cos(), a.sin()).dot(f - corner)
+}
+
+pub fn perlin_noise(x: Vec2, period: Vec2, seed: u32) -> f32 {
+    let i = x.map(f32::floor);
+    let f = x - i;
+    let u = fade(f);
+    let corner = |x, y| perlin_corner(i, f, Vec2::new(x, y), period, seed);
+    let n = mix(
+        mix(corner(0., 0.), corner(1., 0.), u.x),
+        mix(corner(0., 1.), corner(1., 1.), u.x),
+        u.y,
+    );
+    n * consts::FRAC_1_SQRT_2 + 0.5
+}
+
+pub fn value_noise(x: Vec2, period: Vec2, seed: u32) -> f32 {
+    let i = x.map(f32::floor);
+    let u = fade(x - i);
+    let corner = |x, y| hash3(wrap(i + Vec2::new(x, y), period), seed)[0];
+    mix(
+        mix(corner(0., 0.), corner(1., 0.), u.x),
+        mix(corner(0., 1.), corner(1., 1.), u.x),
+        u.y,
+    )
+}
+
+/// Distances to the closest and second closest cell points and random value of the closest cell.
+///
+/// Metric is 0 for euclidean, 1 for manhattan and 2 for chebyshev distance.
+pub fn worley_noise(x: Vec2, period: Vec2, seed: u32, metric: i32) -> [f32; 3] {
+    let i = x.map(f32::floor);
+    let f = x - i;
+    let mut result = [8., 8., 0.];
+    for cy in -2..3 {
+        for cx in -2..3 {
+            let offset = Vec2::new(cx as f32, cy as f32);
+            let h = hash3(wrap(i + offset, period), seed);
+            let r = offset + Vec2::new(h[0], h[1]) - f;
+            let d = match metric {
+                0 => r.length(),
+                1 => r.x.abs() + r.y.abs(),
+                _ => r.x.abs().max(r.y.abs()),
+            };
+            if d < result[0] {
+                result = [d, result[0], h[2]];
+            } else if d < result[1] {
+                result[1] = d;
+            }
+        }
+    }
+    result
+}
+
+/// Hue, saturation and value of a color, all from 0 to 1.
+pub fn rgb_to_hsv(c: [f32; 3]) -> [f32; 3] {
+    let high = c[0].max(c[1]).max(c[2]);
+    let d = high - c[0].min(c[1]).min(c[2]);
+    let h = if d <= 0. {
+        0.
+    } else if high == c[0] {
+        modulo((c[1] - c[2]) / d, 6.)
+    } else if high == c[1] {
+        (c[2] - c[0]) / d + 2.
+    } else {
+        (c[0] - c[1]) / d + 4.
+    };
+    [h / 6., if high > 0. { d / high } else { 0. }, high]
+}
+
+pub fn hsv_to_rgb(c: [f32; 3]) -> [f32; 3] {
+    let channel = |n: f32| {
+        let k = modulo(n + c[0] * 6., 6.);
+        c[2] - c[2] * c[1] * clamp(k.min(4. - k), 0., 1.)
+    };
+    [channel(5.), channel(3.), channel(1.)]
+}
+
+/// Hue, saturation and lightness of a color, all from 0 to 1.
+pub fn rgb_to_hsl(c: [f32; 3]) -> [f32; 3] {
+    let hsv = rgb_to_hsv(c);
+    let l = hsv[2] * (1. - hsv[1] / 2.);
+    let s = if l > 0. && l < 1. {
+        (hsv[2] - l) / l.min(1. - l)
+    } else {
+        0.
+    };
+    [hsv[0], s, l]
+}
+
+pub fn hsl_to_rgb(c: [f32; 3]) -> [f32; 3] {
+    let a = c[1] * c[2].min(1. - c[2]);
+    let channel = |n: f32| {
+        let k = modulo(n + c[0] * 12., 12.);
+        c[2] - a * clamp((k - 3.).min(9. - k), -1., 1.)
+    };
+    [channel(0.), channel(8.), channel(4.)]
+}
+
+/// Signed distance to a box with half size `b` and corners rounded by `r`.
+pub fn box_sdf(p: Vec2, b: Vec2, r: f32) -> f32 {
+    let e = p.map(f32::abs) - b + Vec2::splat(r);
+    e.map(|e| e.max(0.)).length() + e.x.max(e.y).min(0.) - r
+}
+
+fn sign(x: f32) -> f32 {
+    if x > 0. {
+        1.
+    } else if x < 0. {
+        -1.
+    } else {
+        0.
+    }
+}
+
+/// Signed distance to a star with `n` points on the unit circle, one of them pointing up,
+/// and inner vertices at radius `inner`, which is a regular polygon for `inner = cos(pi / n)`.
+pub fn star_sdf(p: Vec2, n: f32, inner: f32) -> f32 {
+    let an = consts::PI / n;
+    let bn = modulo(p.x.atan2(p.y) + an, 2. * an) - an;
+    let p = Vec2::new(bn.cos(), bn.sin().abs()) * p.length();
+    let e = Vec2::new(an.cos(), an.sin()) * inner - Vec2::new(1., 0.);
+    let w = p - Vec2::new(1., 0.);
+    let d = w - e * clamp(w.dot(e) / e.dot(e), 0., 1.);
+    -d.length() * sign(e.x * w.y - e.y * w.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(h: [u32; 3]) -> [f32; 3] {
+        [
+            (h[0] >> 8) as f32 / 16777216.,
+            (h[1] >> 8) as f32 / 16777216.,
+            (h[2] >> 8) as f32 / 16777216.,
+        ]
+    }
+
+    #[test]
+    fn hash3_of_negative_cells() {
+        // GLSL truncates towards zero in ivec2(p) and keeps the bits in uvec3(...).
+        let expected = unit(pcg3d([-1i32 as u32, -2i32 as u32, 7]));
+        assert_eq!(hash3(Vec2::new(-1., -2.), 7), expected);
+        assert_eq!(hash3(Vec2::new(-1.5, -2.5), 7), expected);
+        assert_eq!(unit(pcg3d([0xffff_ffff, 0xffff_fffe, 7])), expected);
+        assert!(hash3(Vec2::new(-1., -2.), 7) != hash3(Vec2::new(1., 2.), 7));
+    }
+}
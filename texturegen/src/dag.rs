@@ -7,7 +7,7 @@ pub struct Edge {
     pub target: u32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Port<Ix: IndexType> {
     pub node: NodeIndex<Ix>,
     pub port: u32,
@@ -51,8 +51,10 @@ impl<N, Ix: IndexType> PortNumbered<N, Ix> {
             .find_edge(&self.dag, |dag, e, _| {
                 dag.edge_weight(e).unwrap().target == trg.port
             });
+        // Nodes can be connected by several edges between different ports, so
+        // this can't use `Dag::update_edge`, which keeps one edge per pair of nodes.
         let result = self.dag
-            .update_edge(
+            .add_edge(
                 src.node,
                 trg.node,
                 Edge {
@@ -61,12 +63,14 @@ impl<N, Ix: IndexType> PortNumbered<N, Ix> {
                 },
             )
             .map_err(Into::into);
-        if let Ok(_) = result {
-            if let Some(e) = replaced {
+        match (result, replaced) {
+            // Removing swaps the last edge, which is the new one, into its place.
+            (Ok(_), Some(e)) => {
                 self.dag.remove_edge(e);
+                Ok(e)
             }
+            (result, _) => result,
         }
-        result
     }
 
     pub fn remove_edge_to_port(&mut self, trg: Port<Ix>) -> Option<Port<Ix>> {
@@ -185,3 +189,37 @@ impl<'a, N: 'a, Ix: IndexType> Iterator for Children<'a, N, Ix> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parents(dag: &PortNumbered<()>, node: NodeIndex) -> Vec<(Port<u32>, u32)> {
+        let mut parents = dag.parents(node).collect::<Vec<_>>();
+        parents.sort_by_key(|&(_, target)| target);
+        parents
+    }
+
+    #[test]
+    fn edges_between_ports_of_the_same_nodes() {
+        let mut dag = PortNumbered::new();
+        let (a, b) = (dag.add_node(()), dag.add_node(()));
+        dag.update_edge(port(a, 0), port(b, 0)).unwrap();
+        dag.update_edge(port(a, 1), port(b, 1)).unwrap();
+        assert_eq!(dag.edge_count(), 2);
+        assert_eq!(parents(&dag, b), vec![(port(a, 0), 0), (port(a, 1), 1)]);
+    }
+
+    #[test]
+    fn update_replaces_edge_to_port() {
+        let mut dag = PortNumbered::new();
+        let (a, b) = (dag.add_node(()), dag.add_node(()));
+        dag.update_edge(port(a, 0), port(b, 0)).unwrap();
+        dag.update_edge(port(a, 1), port(b, 1)).unwrap();
+        dag.update_edge(port(a, 2), port(b, 0)).unwrap();
+        assert_eq!(dag.edge_count(), 2);
+        assert_eq!(parents(&dag, b), vec![(port(a, 2), 0), (port(a, 1), 1)]);
+        assert!(dag.update_edge(port(b, 0), port(a, 0)).is_err());
+        assert_eq!(dag.edge_count(), 2);
+    }
+}
@@ -0,0 +1,418 @@
+//! Saving and loading of whole generator graphs as RON text.
+//!
+//! Nodes are stored in index order so that loading a file gives back the same
+//! `NodeIndex`es, and edges refer to nodes by those indices.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use daggy::NodeIndex;
+use ron;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use {Col, Generator, Vec2};
+use dag::{port, Port};
+use process::{Registry, SettingError, Stop, Value};
+
+#[derive(Serialize, Deserialize)]
+struct File<P> {
+    #[serde(default)]
+    tiling: bool,
+    nodes: Vec<StoredNode<P>>,
+    edges: Vec<StoredEdge>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredNode<P> {
+    process: String,
+    #[serde(default)]
+    label: Option<String>,
+    settings: BTreeMap<String, StoredValue>,
+    data: Option<P>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEdge {
+    from: StoredPort,
+    to: StoredPort,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredPort {
+    node: u32,
+    port: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum StoredValue {
+    Text(String),
+    Integer(u32),
+    Boolean(bool),
+    Float(f32),
+    Color([f32; 4]),
+    Choice(String),
+    Stops(Vec<(f32, [f32; 4])>),
+    Points(Vec<(f32, f32)>),
+}
+
+impl StoredValue {
+    fn new(value: Value) -> StoredValue {
+        match value {
+            Value::Text(t) => StoredValue::Text(t),
+            Value::Integer(i) => StoredValue::Integer(i),
+            Value::Boolean(b) => StoredValue::Boolean(b),
+            Value::Float(f) => StoredValue::Float(f),
+            Value::Color(c) => StoredValue::Color([c.red, c.green, c.blue, c.alpha]),
+            Value::Choice(c) => StoredValue::Choice(c),
+            Value::Stops(s) => StoredValue::Stops(
+                s.into_iter()
+                    .map(|s| {
+                        let c = s.color;
+                        (s.position, [c.red, c.green, c.blue, c.alpha])
+                    })
+                    .collect(),
+            ),
+            Value::Points(p) => StoredValue::Points(p.into_iter().map(|p| (p.x, p.y)).collect()),
+        }
+    }
+
+    fn restore(self) -> Value {
+        match self {
+            StoredValue::Text(t) => Value::Text(t),
+            StoredValue::Integer(i) => Value::Integer(i),
+            StoredValue::Boolean(b) => Value::Boolean(b),
+            StoredValue::Float(f) => Value::Float(f),
+            StoredValue::Color(c) => Value::Color(Col::new(c[0], c[1], c[2], c[3])),
+            StoredValue::Choice(c) => Value::Choice(c),
+            StoredValue::Stops(s) => Value::Stops(
+                s.into_iter()
+                    .map(|(p, c)| Stop::new(p, Col::new(c[0], c[1], c[2], c[3])))
+                    .collect(),
+            ),
+            StoredValue::Points(p) => Value::Points(
+                p.into_iter().map(|(x, y)| Vec2::new(x, y)).collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Serialize(ron::ser::Error),
+    Setting(SettingError),
+}
+
+impl Display for SaveError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Serialize(ref e) => write!(fmt, "Serializing graph failed: {}", e),
+            SaveError::Setting(ref e) => e.fmt(fmt),
+        }
+    }
+}
+
+impl Error for SaveError {
+    fn description(&self) -> &str {
+        "Saving graph failed"
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Syntax(ron::de::Error),
+    UnknownProcess(String),
+    Setting(SettingError),
+    UnknownNode(u32),
+    InvalidInput(Port<u32>),
+    InvalidOutput(Port<u32>),
+    InvalidEdge(Port<u32>, Port<u32>),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        use self::LoadError::*;
+        match *self {
+            Syntax(ref e) => write!(fmt, "Parsing graph failed: {}", e),
+            UnknownProcess(ref p) => write!(fmt, "Unknown process type \"{}\"", p),
+            Setting(ref e) => e.fmt(fmt),
+            UnknownNode(n) => write!(fmt, "There is no node {}", n),
+            InvalidInput(p) => write!(fmt, "Node {} has no input {}", p.node.index(), p.port),
+            InvalidOutput(p) => write!(fmt, "Node {} has no output {}", p.node.index(), p.port),
+            InvalidEdge(from, to) => write!(
+                fmt,
+                "Edge from {}:{} to {}:{} would form a cycle or share an input",
+                from.node.index(),
+                from.port,
+                to.node.index(),
+                to.port
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn description(&self) -> &str {
+        "Loading graph failed"
+    }
+}
+
+impl From<SettingError> for SaveError {
+    fn from(err: SettingError) -> Self {
+        SaveError::Setting(err)
+    }
+}
+
+impl From<SettingError> for LoadError {
+    fn from(err: SettingError) -> Self {
+        LoadError::Setting(err)
+    }
+}
+
+impl From<ron::de::Error> for LoadError {
+    fn from(err: ron::de::Error) -> Self {
+        LoadError::Syntax(err)
+    }
+}
+
+impl<T> Generator<T> {
+    /// Serializes the graph, storing whatever `payload` returns for each node alongside it.
+    pub fn save<P, F>(&self, mut payload: F) -> Result<String, SaveError>
+    where
+        P: Serialize,
+        F: FnMut(&T) -> Option<P>,
+    {
+        let mut nodes = vec![];
+        for (i, (process, data)) in self.iter().enumerate() {
+            let mut settings = BTreeMap::new();
+            for descriptor in process.settings() {
+                let value = process.setting(descriptor.name)?.to_value();
+                settings.insert(descriptor.name.into(), StoredValue::new(value));
+            }
+            nodes.push(StoredNode {
+                process: process.name().into(),
+                label: self.label(NodeIndex::new(i)).map(Into::into),
+                settings: settings,
+                data: payload(data),
+            });
+        }
+        let edges = self.iter_connections()
+            .map(|(from, to)| StoredEdge {
+                from: StoredPort {
+                    node: from.node.index() as u32,
+                    port: from.port,
+                },
+                to: StoredPort {
+                    node: to.node.index() as u32,
+                    port: to.port,
+                },
+            })
+            .collect();
+        let file = File {
+            tiling: self.tiling(),
+            nodes: nodes,
+            edges: edges,
+        };
+        ron::ser::to_string_pretty(&file, Default::default()).map_err(SaveError::Serialize)
+    }
+
+    /// Recreates a graph saved with `save`, building each node's data from its stored payload.
+    ///
+    /// Processes are looked up by name from `registry`.
+    pub fn load<P, F>(
+        source: &str,
+        registry: &Registry,
+        mut data: F,
+    ) -> Result<Generator<T>, LoadError>
+    where
+        P: DeserializeOwned,
+        F: FnMut(Option<P>) -> T,
+    {
+        let file: File<P> = ron::de::from_str(source)?;
+        let mut gen = Generator::new();
+        gen.set_tiling(file.tiling);
+        for node in file.nodes {
+            let mut process = registry
+                .create(&node.process)
+                .ok_or_else(|| LoadError::UnknownProcess(node.process.clone()))?;
+            for (key, value) in node.settings {
+                process.set(&key, value.restore())?;
+            }
+            let n = gen.add(process, data(node.data));
+            gen.set_label(n, node.label);
+        }
+        let mut targets = HashSet::new();
+        for edge in file.edges {
+            let from = gen.stored_port(edge.from)?;
+            let to = gen.stored_port(edge.to)?;
+            if from.port >= gen.get(from.node).unwrap().0.max_out() {
+                return Err(LoadError::InvalidOutput(from));
+            }
+            if to.port >= gen.get(to.node).unwrap().0.max_in() {
+                return Err(LoadError::InvalidInput(to));
+            }
+            if !targets.insert(to) || !gen.connect(from, to) {
+                return Err(LoadError::InvalidEdge(from, to));
+            }
+        }
+        Ok(gen)
+    }
+
+    fn stored_port(&self, stored: StoredPort) -> Result<Port<u32>, LoadError> {
+        let node = NodeIndex::new(stored.node as usize);
+        if self.get(node).is_some() {
+            Ok(port(node, stored.port))
+        } else {
+            Err(LoadError::UnknownNode(stored.node))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(registry: &Registry, name: &str) -> Box<::process::Process> {
+        registry.create(name).unwrap()
+    }
+
+    fn round_trip(gen: &Generator<Option<u32>>, registry: &Registry) -> Generator<Option<u32>> {
+        Generator::load(&gen.save(|d| *d).unwrap(), registry, |d| d).unwrap()
+    }
+
+    fn load(source: &str) -> Result<Generator<()>, LoadError> {
+        Generator::load(source, &Registry::builtin(), |_: Option<()>| ())
+    }
+
+    /// File with a constant and an invert node connected by the given edge.
+    fn edge(from: (u32, u32), to: (u32, u32)) -> String {
+        format!(
+            "(nodes: [(process: \"constant\", settings: {{}}, data: None), \
+             (process: \"invert\", settings: {{}}, data: None)], \
+             edges: [(from: (node: {}, port: {}), to: (node: {}, port: {}))])",
+            from.0, from.1, to.0, to.1
+        )
+    }
+
+    #[test]
+    fn save_load_save() {
+        let registry = Registry::builtin();
+        let mut gen = Generator::new();
+        gen.set_tiling(true);
+        let bricks = gen.add(create(&registry, "bricks"), Some(1));
+        gen.set_label(bricks, Some("walls".into()));
+        let split = gen.add(create(&registry, "split_channels"), None);
+        let combine = gen.add(create(&registry, "combine_channels"), Some(3));
+        let mut map = create(&registry, "gradient_map");
+        let stops = vec![
+            Stop::new(0.25, Col::new(1., 0., 0., 1.)),
+            Stop::new(0.75, Col::new(0., 0., 1., 0.5)),
+        ];
+        map.set("stops", Value::Stops(stops)).unwrap();
+        let map = gen.add(map, None);
+        let mut curves = create(&registry, "curves");
+        let points = vec![Vec2::new(0., 0.2), Vec2::new(0.5, 0.8), Vec2::new(1., 1.)];
+        curves.set("points", Value::Points(points)).unwrap();
+        let curves = gen.add(curves, Some(5));
+        gen.connect(port(bricks, 1), port(split, 0));
+        for i in 0..4 {
+            gen.connect(port(split, i), port(combine, 3 - i));
+        }
+        gen.connect(port(bricks, 2), port(map, 0));
+        gen.connect(port(combine, 0), port(curves, 0));
+
+        let saved = gen.save(|d| *d).unwrap();
+        let loaded = round_trip(&gen, &registry);
+        assert_eq!(loaded.save(|d| *d).unwrap(), saved);
+        assert!(loaded.tiling());
+        assert_eq!(loaded.label(bricks), Some("walls"));
+        assert_eq!(loaded.get(curves).map(|n| *n.1), Some(Some(5)));
+        let mut edges = loaded.iter_connections().collect::<Vec<_>>();
+        edges.sort_by_key(|&(_, to)| (to.node, to.port));
+        assert_eq!(
+            &edges[1..5],
+            &[
+                (port(split, 3), port(combine, 0)),
+                (port(split, 2), port(combine, 1)),
+                (port(split, 1), port(combine, 2)),
+                (port(split, 0), port(combine, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_after_remove() {
+        let registry = Registry::builtin();
+        let mut gen = Generator::new();
+        let nodes = ["constant", "invert", "noise", "blend"]
+            .iter()
+            .map(|&name| {
+                let n = gen.add(create(&registry, name), None);
+                gen.set_label(n, Some(name.into()));
+                n
+            })
+            .collect::<Vec<_>>();
+        gen.connect(port(nodes[0], 0), port(nodes[3], 0));
+        gen.connect(port(nodes[2], 0), port(nodes[3], 1));
+        gen.connect(port(nodes[0], 0), port(nodes[1], 0));
+        gen.remove(&nodes[1]);
+
+        let loaded = round_trip(&gen, &registry);
+        assert_eq!(loaded.save(|d| *d).unwrap(), gen.save(|d| *d).unwrap());
+        let labels = loaded.iter_connections()
+            .map(|(from, to)| (loaded.label(from.node), loaded.label(to.node), to.port))
+            .collect::<Vec<_>>();
+        assert_eq!(labels.len(), 2);
+        assert!(labels.contains(&(Some("constant"), Some("blend"), 0)));
+        assert!(labels.contains(&(Some("noise"), Some("blend"), 1)));
+    }
+
+    #[test]
+    fn unknown_process() {
+        let source = "(nodes: [(process: \"nothing\", settings: {}, data: None)], edges: [])";
+        match load(source) {
+            Err(LoadError::UnknownProcess(ref p)) if p == "nothing" => {}
+            _ => panic!("Loaded unknown process"),
+        }
+    }
+
+    #[test]
+    fn unknown_node() {
+        match load(&edge((2, 0), (1, 0))) {
+            Err(LoadError::UnknownNode(2)) => {}
+            _ => panic!("Loaded edge from unknown node"),
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        let to = port(NodeIndex::new(1), 1);
+        match load(&edge((0, 0), (1, 1))) {
+            Err(LoadError::InvalidInput(p)) if p == to => {}
+            _ => panic!("Loaded edge to missing input"),
+        }
+    }
+
+    #[test]
+    fn invalid_output() {
+        let from = port(NodeIndex::new(0), 1);
+        match load(&edge((0, 1), (1, 0))) {
+            Err(LoadError::InvalidOutput(p)) if p == from => {}
+            _ => panic!("Loaded edge from missing output"),
+        }
+    }
+
+    #[test]
+    fn invalid_edge() {
+        let source = "(nodes: [(process: \"invert\", settings: {}, data: None), \
+                      (process: \"invert\", settings: {}, data: None)], \
+                      edges: [(from: (node: 0, port: 0), to: (node: 1, port: 0)), \
+                      (from: (node: 1, port: 0), to: (node: 0, port: 0))])";
+        let (a, b) = (NodeIndex::new(0), NodeIndex::new(1));
+        match load(source) {
+            Err(LoadError::InvalidEdge(from, to)) if (from, to) == (port(b, 0), port(a, 0)) => {}
+            _ => panic!("Loaded edge forming a cycle"),
+        }
+    }
+}
@@ -1,13 +1,23 @@
+//! Procedural textures built from graphs of processes, rendered either with
+//! generated GLSL shaders or on the CPU.
+//!
+//! Random values of the processes come from an integer hash of their seed, so
+//! the same graph with the same seeds gives the same texture on every run and
+//! on both backends, up to floating point rounding.
+
 #[macro_use]
 extern crate custom_derive;
 extern crate daggy;
 #[macro_use]
 extern crate enum_derive;
+extern crate ron;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 
 pub extern crate palette;
 
 use std::slice;
-use std::collections::HashSet;
 use std::ops::Deref;
 
 use daggy::{NodeIndex, PetGraph};
@@ -16,15 +26,20 @@ use daggy::petgraph::visit::Bfs;
 
 use dag::PortNumbered;
 use process::Process;
-use shader::{Context, Shader};
 
-pub use shader::Source;
+pub use shader::{col, Context, Identifier, ShaderError, Source};
 pub use dag::{port, Edge, Port};
+pub use cpu::{Sampler, Texture, Vec2, Vec4};
+pub use format::{LoadError, SaveError};
+pub use pass::{reduced_size, reduction, Binding, Pass, Schedule, Target, REDUCTION_BLOCK};
 
 pub type Col = palette::Srgba;
 
 pub mod process;
+mod cpu;
 mod dag;
+mod format;
+mod pass;
 mod shader;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -36,6 +51,7 @@ pub enum EventType {
 
 pub struct Generator<T> {
     dag: PortNumbered<Node<T>>,
+    tiling: bool,
 }
 
 pub struct GeneratorView<'a, T: 'a>(&'a Generator<T>);
@@ -57,12 +73,15 @@ impl<T> Generator<T> {
     pub fn new() -> Generator<T> {
         Generator {
             dag: PortNumbered::new(),
+            tiling: false,
         }
     }
 
+    /// Hands the schedule rendering the first output of each node changed since the last view
+    /// to `fun`, for updating previews of the nodes.
     pub fn view<F>(&mut self, mut fun: F) -> GeneratorView<T>
     where
-        F: FnMut(Source, &mut T, &Process),
+        F: FnMut(Result<Schedule, ShaderError>, &mut T, &Process),
     {
         for node in 0..self.dag.node_count() {
             let node = NodeIndex::new(node);
@@ -71,10 +90,9 @@ impl<T> Generator<T> {
                 .map(|n| n.dirty)
                 .unwrap_or(false)
             {
-                self.update_dag(node);
-                let program = build_shader(&self.dag, node);
+                let schedule = pass::schedule(&self.dag, port(node, 0), self.tiling);
                 if let Some(n) = self.dag.node_weight_mut(node) {
-                    fun(program, &mut n.data, &*n.process);
+                    fun(schedule, &mut n.data, &*n.process);
                     n.dirty = false;
                 }
             }
@@ -95,6 +113,43 @@ impl<T> Generator<T> {
         self.dag.node_weight_mut(node).map(|n| &mut n.data)
     }
 
+    /// Whether outputs wrap seamlessly at the texture edges so that they can be tiled.
+    pub fn tiling(&self) -> bool {
+        self.tiling
+    }
+
+    pub fn set_tiling(&mut self, tiling: bool) {
+        if self.tiling != tiling {
+            self.tiling = tiling;
+            for n in 0..self.dag.node_count() {
+                if let Some(n) = self.dag.node_weight_mut(NodeIndex::new(n)) {
+                    n.dirty = true;
+                }
+            }
+        }
+    }
+
+    pub fn label(&self, node: NodeIndex) -> Option<&str> {
+        self.dag
+            .node_weight(node)
+            .and_then(|n| n.label.as_ref().map(|l| &l[..]))
+    }
+
+    pub fn set_label(&mut self, node: NodeIndex, label: Option<String>) {
+        if let Some(n) = self.dag.node_weight_mut(node) {
+            n.label = label;
+        }
+    }
+
+    /// Finds the first node with given label.
+    pub fn find(&self, label: &str) -> Option<NodeIndex> {
+        self.dag
+            .raw_nodes()
+            .iter()
+            .position(|n| n.weight.label.as_ref().map(|l| l == label).unwrap_or(false))
+            .map(NodeIndex::new)
+    }
+
     pub fn add(&mut self, node: Box<Process>, data: T) -> NodeIndex {
         let n = self.dag.add_node(Node::new(node, data));
         self.dirtify(n);
@@ -135,6 +190,35 @@ impl<T> Generator<T> {
         }
     }
 
+    /// Renders the output on the CPU without needing a GPU context.
+    ///
+    /// This is slow compared to running the generated shader, but gives the same
+    /// result and so can be used headlessly or as a reference.
+    pub fn evaluate(&self, output: Port<u32>, width: u32, height: u32) -> Option<Texture> {
+        match self.dag.node_weight(output.node) {
+            Some(n) if output.port < n.process.max_out() => {
+                Some(cpu::render(&self.dag, output, width, height, self.tiling))
+            }
+            _ => None,
+        }
+    }
+
+    /// Splits rendering of the output into passes, each rendering one port to a texture.
+    ///
+    /// Ports read by sampled inputs or by several inputs get their own pass and
+    /// are read from a texture by the later passes instead of being inlined.
+    pub fn schedule(&self, output: Port<u32>) -> Result<Schedule, ShaderError> {
+        match self.dag.node_weight(output.node) {
+            Some(n) if output.port < n.process.max_out() => {
+                pass::schedule(&self.dag, output, self.tiling)
+            }
+            _ => Err(ShaderError::MissingOutput {
+                node: output.node.index(),
+                index: output.port,
+            }),
+        }
+    }
+
     pub fn graph(&self) -> &PetGraph<Node<T>, ::dag::Edge, u32> {
         self.dag.graph()
     }
@@ -159,62 +243,12 @@ impl<T> Generator<T> {
             }
         }
     }
-
-    //TODO: Switch this to use topological order and cache more inteligently.
-    fn update_dag(&mut self, node: NodeIndex) {
-        let mut stack = vec![node];
-        while let Some(node) = stack.pop() {
-            self.dag.node_weight_mut(node).unwrap().program = Some(build_shader(&self.dag, node));
-            stack.extend(self.dag.children(node).map(|n| n.1.node));
-        }
-    }
-}
-
-fn build_shader<T>(dag: &PortNumbered<Node<T>>, node: NodeIndex) -> Source {
-    let mut result = Shader::new();
-    result.add_vertex("gl_Position = matrix * vec4(position, 0, 1);\n");
-    result.add_fragment("vec4 one = vec4(1);\n");
-    gather_shader(dag, &mut result, node, &mut HashSet::new());
-    result.add_fragment(format!("color = out_{}_0;\n", node.index()));
-    result.build()
-}
-
-fn gather_shader<T>(
-    dag: &PortNumbered<Node<T>>,
-    shader: &mut Shader,
-    node: NodeIndex,
-    visited: &mut HashSet<NodeIndex>,
-) {
-    if visited.contains(&node) {
-        return;
-    }
-    visited.insert(node);
-    let process = &dag.node_weight(node)
-        .expect("Node or it's parent didn't exist.")
-        .process;
-    for s in 0..process.max_in() {
-        shader.add_fragment(format!("vec4 in_{}_{} = vec4(0);\n", node.index(), s));
-    }
-    let mut inputs = HashSet::new();
-    for (parent, target) in dag.parents(node) {
-        inputs.insert(target);
-        gather_shader(dag, shader, parent.node, visited);
-        shader.add_fragment(format!(
-            "in_{}_{} = out_{}_{};\n",
-            node.index(),
-            target,
-            parent.node.index(),
-            parent.port
-        ));
-    }
-    let mut context = Context::new(node.index(), inputs, process.max_out());
-    shader.add_fragment(process.shader(&mut context));
 }
 
 pub struct Node<T> {
     data: T,
+    label: Option<String>,
     process: Box<Process>,
-    program: Option<Source>,
     dirty: bool,
 }
 
@@ -222,8 +256,8 @@ impl<T> Node<T> {
     fn new(process: Box<Process>, data: T) -> Node<T> {
         Node {
             data: data,
+            label: None,
             process: process,
-            program: None,
             dirty: true,
         }
     }
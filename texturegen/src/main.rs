@@ -0,0 +1,178 @@
+//! Renders outputs of a saved graph to PNG files without opening a window.
+//!
+//! Usage: texturegen <graph> [--size <width>x<height>] [--tiling] <output>...
+//!
+//! Graphs saved with tiling enabled are always rendered tileable, `--tiling`
+//! enables it for the rest.
+//!
+//! Each output is either a node index or a label, optionally followed by `:<port>`
+//! and `=<file>`. Without a file the output is written to `<output>.png`, or to
+//! `<output>.<port>.png` for ports other than the first.
+
+extern crate daggy;
+extern crate image;
+extern crate serde;
+extern crate texturegen;
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process;
+
+use daggy::NodeIndex;
+use serde::de::IgnoredAny;
+
+use texturegen::{port, Generator, Port};
+use texturegen::process::Registry;
+
+const USAGE: &str = "Usage: texturegen <graph> [--size <width>x<height>] [--tiling] <output>...";
+
+struct Output {
+    name: String,
+    port: u32,
+    path: PathBuf,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("texturegen: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut args = env::args().skip(1);
+    let mut graph = None;
+    let mut size = (1024, 1024);
+    let mut tiling = false;
+    let mut outputs = vec![];
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-s" | "--size" => {
+                let s = args.next().ok_or("Missing value for --size")?;
+                size = parse_size(&s).ok_or_else(|| format!("Invalid size \"{}\"", s))?;
+            }
+            "-t" | "--tiling" => tiling = true,
+            _ if graph.is_none() => graph = Some(arg),
+            _ => outputs.push(parse_output(&arg)?),
+        }
+    }
+    let graph = graph.ok_or(USAGE)?;
+    if outputs.is_empty() {
+        return Err("No outputs given".into());
+    }
+    check_paths(&outputs)?;
+
+    let mut source = String::new();
+    File::open(&graph)
+        .and_then(|mut f| f.read_to_string(&mut source))
+        .map_err(|e| format!("Reading \"{}\" failed: {}", graph, e))?;
+    let mut gen = Generator::load(&source, &Registry::builtin(), |_: Option<IgnoredAny>| ())
+        .map_err(|e| format!("Loading \"{}\" failed: {}", graph, e))?;
+    if tiling {
+        gen.set_tiling(true);
+    }
+
+    for output in outputs {
+        let node = output_port(&gen, &output)?;
+        let texture = gen.evaluate(node, size.0, size.1)
+            .ok_or_else(|| format!("There is no node \"{}\"", output.name))?;
+        image::save_buffer(
+            &output.path,
+            &texture.to_rgba8(),
+            texture.width(),
+            texture.height(),
+            image::RGBA(8),
+        ).map_err(|e| format!("Writing \"{}\" failed: {}", output.path.display(), e))?;
+    }
+    Ok(())
+}
+
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.split('x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+fn parse_output(s: &str) -> Result<Output, String> {
+    let (spec, path) = match s.find('=') {
+        Some(i) => (&s[..i], Some(PathBuf::from(&s[i + 1..]))),
+        None => (s, None),
+    };
+    let (name, port) = match spec.rfind(':') {
+        Some(i) => {
+            let port = spec[i + 1..]
+                .parse()
+                .map_err(|_| format!("Invalid port in \"{}\"", s))?;
+            (&spec[..i], port)
+        }
+        None => (spec, 0),
+    };
+    let path = path.unwrap_or_else(|| match port {
+        0 => PathBuf::from(format!("{}.png", name)),
+        _ => PathBuf::from(format!("{}.{}.png", name, port)),
+    });
+    Ok(Output {
+        name: name.into(),
+        port: port,
+        path: path,
+    })
+}
+
+fn check_paths(outputs: &[Output]) -> Result<(), String> {
+    let mut paths = HashSet::new();
+    for output in outputs {
+        if !paths.insert(&output.path) {
+            return Err(format!("Several outputs written to \"{}\"", output.path.display()));
+        }
+    }
+    Ok(())
+}
+
+fn output_port<T>(gen: &Generator<T>, output: &Output) -> Result<Port<u32>, String> {
+    let node = gen.find(&output.name)
+        .or_else(|| output.name.parse().ok().map(NodeIndex::new))
+        .ok_or_else(|| format!("There is no node \"{}\"", output.name))?;
+    let process = gen.get(node)
+        .ok_or_else(|| format!("There is no node \"{}\"", output.name))?
+        .0;
+    if output.port >= process.max_out() {
+        return Err(format!(
+            "Node \"{}\" has no output {}",
+            output.name, output.port
+        ));
+    }
+    Ok(port(node, output.port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_path_keeps_dots_of_name() {
+        let output = parse_output("wall.albedo:1").unwrap();
+        assert_eq!((&output.name[..], output.port), ("wall.albedo", 1));
+        assert_eq!(output.path, PathBuf::from("wall.albedo.1.png"));
+        let output = parse_output("wall.albedo:0").unwrap();
+        assert_eq!(output.path, PathBuf::from("wall.albedo.png"));
+        let output = parse_output("wall.albedo=out/albedo.png").unwrap();
+        assert_eq!(output.path, PathBuf::from("out/albedo.png"));
+    }
+
+    #[test]
+    fn outputs_to_the_same_path() {
+        let outputs = |specs: &[&str]| {
+            specs.iter().map(|s| parse_output(s).unwrap()).collect::<Vec<_>>()
+        };
+        assert!(check_paths(&outputs(&["bricks", "bricks:1", "bricks:2"])).is_ok());
+        assert!(check_paths(&outputs(&["bricks", "bricks:0"])).is_err());
+        assert!(check_paths(&outputs(&["bricks:1", "3=bricks.1.png"])).is_err());
+    }
+}
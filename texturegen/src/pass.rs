@@ -0,0 +1,454 @@
+//! Splitting of the graph into passes rendered to intermediate textures.
+//!
+//! Everything between two pass boundaries is inlined into a single fragment
+//! shader, and boundary ports are read back with `texture` from the result of
+//! an earlier pass. Processes needing the range of an input over the whole
+//! texture get it from a 2x1 texture, which is found by reducing such a
+//! texture with `reduction`.
+//! Processes rendered in several stages get a pass for each stage but the
+//! last, which reads the one before it like a boundary.
+
+use std::collections::{HashMap, HashSet};
+
+use daggy::NodeIndex;
+
+use Node;
+use dag::{port, Port, PortNumbered};
+use shader::{function, range, stage, texture, Context, Shader, ShaderError, Source};
+
+/// Texture rendered by an earlier pass bound to a sampler uniform.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub uniform: String,
+    /// Index of the pass in the schedule.
+    pub pass: usize,
+}
+
+/// What a pass renders to its texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// Output port, which is rendered by the last stage of its process.
+    Port(Port<u32>),
+    /// Stage before the last of a process that has several, which renders its first output.
+    Stage(NodeIndex, u32),
+}
+
+impl Target {
+    pub fn node(&self) -> NodeIndex {
+        match *self {
+            Target::Port(p) => p.node,
+            Target::Stage(node, _) => node,
+        }
+    }
+}
+
+/// Shader rendering a single output port or stage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pass {
+    pub target: Target,
+    pub source: Source,
+    pub inputs: Vec<Binding>,
+    /// Passes whose minimum and maximum are read from a sampler uniform.
+    ///
+    /// The renderer binds these to the 2x1 texture that `reduction` reduces the
+    /// texture of the pass to, holding the minimum on the left and the maximum on the right.
+    pub ranges: Vec<Binding>,
+}
+
+/// Passes in the order they have to be rendered in, last of which renders the requested output.
+///
+/// All passes are meant to be rendered at the resolution of the final output,
+/// to float textures storing colors without sRGB conversion, sampled with linear filtering.
+/// Coordinates are clamped to the edge, or repeated when tiling.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    pub passes: Vec<Pass>,
+    pub tiling: bool,
+}
+
+impl Schedule {
+    pub fn output(&self) -> &Pass {
+        self.passes.last().expect("Schedule should always have the output pass.")
+    }
+}
+
+pub fn schedule<T>(
+    dag: &PortNumbered<Node<T>>,
+    output: Port<u32>,
+    tiling: bool,
+) -> Result<Schedule, ShaderError> {
+    let boundaries = boundaries(dag, output.node);
+    let mut passes = vec![];
+    let mut indices = HashMap::new();
+    for target in order(dag, output, &boundaries) {
+        let mut textures = boundaries.clone();
+        if let Target::Port(p) = target {
+            textures.remove(&p);
+        }
+        let (source, gathered) = build_pass(dag, target, &textures, tiling)?;
+        let inputs = gathered
+            .sampled
+            .into_iter()
+            .map(|t| Binding {
+                uniform: match t {
+                    Target::Port(p) => texture(p.node.index(), p.port),
+                    Target::Stage(node, s) => stage(node.index(), s),
+                }.to_string(),
+                pass: indices[&t],
+            })
+            .collect();
+        let ranges = gathered
+            .reduced
+            .into_iter()
+            .map(|p| Binding {
+                uniform: range(p.node.index(), p.port).to_string(),
+                pass: indices[&Target::Port(p)],
+            })
+            .collect();
+        indices.insert(target, passes.len());
+        passes.push(Pass {
+            target: target,
+            source: source,
+            inputs: inputs,
+            ranges: ranges,
+        });
+    }
+    Ok(Schedule {
+        passes: passes,
+        tiling: tiling,
+    })
+}
+
+/// Factor `reduction` shrinks both dimensions of the reduced area by in each step.
+pub const REDUCTION_BLOCK: u32 = 8;
+
+/// Shader finding the minimum and maximum of a texture in steps, which is rendered
+/// repeatedly until the result is 2x1 texels.
+///
+/// Each step reduces blocks of `REDUCTION_BLOCK` texels in the lower left `size` of the
+/// `source` uniform, writing their minimums to the left half of a target that is `size`
+/// divided by the block rounded up, but twice as wide, and maximums to the right half.
+/// The first step reduces the texture of a pass, after which `split` has to be set so that
+/// maximums are read from the right half of the previous step.
+pub fn reduction() -> Source {
+    let mut result = Shader::new();
+    result.add_vertex("gl_Position = matrix * vec4(position, 0, 1);\n");
+    result.add_function("uniform sampler2D source;\nuniform ivec2 size;\nuniform bool split;\n");
+    result.add_fragment(format!(
+        "ivec2 cells = (size + {n} - 1) / {n};\n\
+         ivec2 texel = ivec2(gl_FragCoord.xy);\n\
+         bool maximum = texel.x >= cells.x;\n\
+         ivec2 block = ivec2(maximum ? texel.x - cells.x : texel.x, texel.y) * {n};\n\
+         int offset = split && maximum ? size.x : 0;\n\
+         color = texelFetch(source, ivec2(block.x + offset, block.y), 0);\n\
+         for(int y = 0; y < {n}; y++) {{\n\
+         for(int x = 0; x < {n}; x++) {{\n\
+         ivec2 p = block + ivec2(x, y);\n\
+         if(p.x < size.x && p.y < size.y) {{\n\
+         vec4 v = texelFetch(source, ivec2(p.x + offset, p.y), 0);\n\
+         color = maximum ? max(color, v) : min(color, v);\n\
+         }}\n}}\n}}\n",
+        n = REDUCTION_BLOCK
+    ));
+    result.build()
+}
+
+/// Number of blocks a step of `reduction` reduces the lower left `size` of its source into,
+/// which is the size of the next area to reduce.
+///
+/// The step ends the reduction once this is 1x1.
+pub fn reduced_size(size: (u32, u32)) -> (u32, u32) {
+    (size.0.div_ceil(REDUCTION_BLOCK), size.1.div_ceil(REDUCTION_BLOCK))
+}
+
+/// Ports whose value needs to be rendered to a texture before rendering `output`.
+///
+/// These are the ports connected to a sampled or reduced input or to more than one input.
+pub fn boundaries<T>(dag: &PortNumbered<Node<T>>, output: NodeIndex) -> HashSet<Port<u32>> {
+    let mut result = HashSet::new();
+    let mut consumers = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![output];
+    while let Some(node) = stack.pop() {
+        if !visited.insert(node) {
+            continue;
+        }
+        let process = &dag.node_weight(node)
+            .expect("Node or it's parent didn't exist.")
+            .process;
+        for (parent, target) in dag.parents(node) {
+            *consumers.entry(parent).or_insert(0) += 1;
+            if process.sampled(target) || process.reduced(target) {
+                result.insert(parent);
+            }
+            stack.push(parent.node);
+        }
+    }
+    result.extend(consumers.into_iter().filter(|&(_, n)| n > 1).map(|(p, _)| p));
+    result
+}
+
+/// Boundaries and earlier stages in an order where each comes after the ones it depends on,
+/// followed by the output.
+pub fn order<T>(
+    dag: &PortNumbered<Node<T>>,
+    output: Port<u32>,
+    boundaries: &HashSet<Port<u32>>,
+) -> Vec<Target> {
+    fn visit<T>(
+        dag: &PortNumbered<Node<T>>,
+        node: NodeIndex,
+        boundaries: &HashSet<Port<u32>>,
+        visited: &mut HashSet<NodeIndex>,
+        result: &mut Vec<Target>,
+    ) {
+        if !visited.insert(node) {
+            return;
+        }
+        for (parent, _) in dag.parents(node) {
+            visit(dag, parent.node, boundaries, visited, result);
+        }
+        let process = &dag.node_weight(node).unwrap().process;
+        result.extend((0..process.stages() - 1).map(|s| Target::Stage(node, s)));
+        result.extend(
+            (0..process.max_out())
+                .map(|p| port(node, p))
+                .filter(|p| boundaries.contains(p))
+                .map(Target::Port),
+        );
+    }
+    let mut result = vec![];
+    visit(dag, output.node, boundaries, &mut HashSet::new(), &mut result);
+    result.retain(|t| *t != Target::Port(output));
+    result.push(Target::Port(output));
+    result
+}
+
+/// Stage a process is evaluated at when rendering the target, which is the last one unless the
+/// target is an earlier stage of it.
+pub fn stage_of<T>(dag: &PortNumbered<Node<T>>, node: NodeIndex, target: Target) -> u32 {
+    match target {
+        Target::Stage(n, s) if n == node => s,
+        _ => dag.node_weight(node).unwrap().process.stages() - 1,
+    }
+}
+
+/// Nodes already inlined into a shader and the textures and ranges they read.
+#[derive(Default)]
+struct Gathered {
+    visited: HashSet<NodeIndex>,
+    sampled: Vec<Target>,
+    reduced: Vec<Port<u32>>,
+}
+
+/// Builds shader rendering `target` which reads `textures` instead of evaluating them.
+///
+/// Returns the textures and ranges that were actually needed along with the shader.
+fn build_pass<T>(
+    dag: &PortNumbered<Node<T>>,
+    target: Target,
+    textures: &HashSet<Port<u32>>,
+    tiling: bool,
+) -> Result<(Source, Gathered), ShaderError> {
+    let mut result = Shader::new();
+    let mut gathered = Gathered::default();
+    result.add_vertex("gl_Position = matrix * vec4(position, 0, 1);\n");
+    gather_shader(dag, &mut result, target, target.node(), textures, tiling, &mut gathered)?;
+    let output = match target {
+        Target::Port(p) => p,
+        Target::Stage(node, _) => port(node, 0),
+    };
+    result.add_fragment(format!(
+        "color = {}(v_tex_coords);\n",
+        function(output.node.index(), output.port)
+    ));
+    Ok((result.build(), gathered))
+}
+
+/// Adds functions evaluating each output of `node` at given texture coordinates, or only the
+/// first one when the pass renders an earlier stage of it.
+///
+/// Parents are defined first so that processes can sample them at arbitrary coordinates.
+fn gather_shader<T>(
+    dag: &PortNumbered<Node<T>>,
+    shader: &mut Shader,
+    rendered: Target,
+    node: NodeIndex,
+    textures: &HashSet<Port<u32>>,
+    tiling: bool,
+    gathered: &mut Gathered,
+) -> Result<(), ShaderError> {
+    if !gathered.visited.insert(node) {
+        return Ok(());
+    }
+    let process = &dag.node_weight(node)
+        .expect("Node or it's parent didn't exist.")
+        .process;
+    let mut inputs = HashMap::new();
+    let mut ranges = HashMap::new();
+    for (parent, target) in dag.parents(node) {
+        inputs.insert(target, parent);
+        if textures.contains(&parent) && process.reduced(target) {
+            ranges.insert(target, parent);
+            if !gathered.reduced.contains(&parent) {
+                gathered.reduced.push(parent);
+                shader.add_function(format!(
+                    "uniform sampler2D {};\n",
+                    range(parent.node.index(), parent.port)
+                ));
+            }
+        }
+        if !textures.contains(&parent) {
+            gather_shader(dag, shader, rendered, parent.node, textures, tiling, gathered)?;
+        } else if !gathered.sampled.contains(&Target::Port(parent)) {
+            gathered.sampled.push(Target::Port(parent));
+            shader.add_function(format!(
+                "uniform sampler2D {t};\n\
+                 vec4 {}(vec2 coords) {{\nreturn texture({t}, coords);\n}}\n",
+                function(parent.node.index(), parent.port),
+                t = texture(parent.node.index(), parent.port)
+            ));
+        }
+    }
+    let mut body = String::new();
+    for s in 0..process.max_in() {
+        let value = match inputs.get(&s) {
+            Some(parent) => format!("{}(coords)", function(parent.node.index(), parent.port)),
+            None => "vec4(0)".into(),
+        };
+        body.push_str(&format!("vec4 in_{}_{} = {};\n", node.index(), s, value));
+    }
+    let current = stage_of(dag, node, rendered);
+    if current > 0 {
+        gathered.sampled.push(Target::Stage(node, current - 1));
+        shader.add_function(format!(
+            "uniform sampler2D {};\n",
+            stage(node.index(), current - 1)
+        ));
+    }
+    let mut context = Context::new(node.index(), inputs, process.max_out(), tiling)
+        .with_ranges(ranges)
+        .with_stage(current);
+    body.push_str(&process.shader(&mut context)?);
+    let last = current + 1 == process.stages();
+    let outputs = if last { process.max_out() } else { 1 };
+    for p in 0..outputs {
+        if last && textures.contains(&port(node, p)) {
+            continue;
+        }
+        shader.add_function(format!(
+            "vec4 {}(vec2 coords) {{\n{}return {};\n}}\n",
+            function(node.index(), p),
+            body,
+            context.output(p)?
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Generator;
+    use process::{Blur, BlurType, ChannelMode, Levels, Noise};
+
+    #[test]
+    fn blur_renders_earlier_stage_first() {
+        let mut g = Generator::new();
+        let n = g.add(Noise::new(0, 2, 2), ());
+        let b = g.add(Blur::new(0.01, 0., BlurType::Gaussian), ());
+        g.connect(port(n, 0), port(b, 0));
+        let schedule = schedule(&g.dag, port(b, 0), false).unwrap();
+        let targets = schedule.passes.iter().map(|p| p.target).collect::<Vec<_>>();
+        let expected = vec![
+            Target::Port(port(n, 0)),
+            Target::Stage(b, 0),
+            Target::Port(port(b, 0)),
+        ];
+        assert_eq!(targets, expected);
+        let inputs = |i: usize| {
+            let mut passes = schedule.passes[i].inputs.iter().map(|b| b.pass).collect::<Vec<_>>();
+            passes.sort();
+            passes
+        };
+        assert_eq!(inputs(1), vec![0]);
+        assert_eq!(inputs(2), vec![0, 1]);
+    }
+
+    #[test]
+    fn auto_levels_reads_reduced_range() {
+        let mut g = Generator::new();
+        let n = g.add(Noise::new(0, 2, 2), ());
+        let l = g.add(Levels::new(ChannelMode::Rgb, true), ());
+        g.connect(port(n, 0), port(l, 0));
+        let schedule = schedule(&g.dag, port(l, 0), false).unwrap();
+        assert_eq!(schedule.passes.len(), 2);
+        let uniform = range(n.index(), 0).to_string();
+        let output = schedule.output();
+        assert_eq!(
+            output.ranges,
+            vec![Binding {
+                uniform: uniform.clone(),
+                pass: 0,
+            }]
+        );
+        let declaration = format!("uniform sampler2D {};", uniform);
+        assert!(output.source.fragment.contains(&declaration));
+    }
+
+    #[test]
+    fn reduction_covers_partial_blocks() {
+        let (width, height) = (513, 7);
+        let mut values = (0..width * height)
+            .map(|i| ((i * 7919) % 1009) as f32)
+            .collect::<Vec<_>>();
+        values[(6 * width + 512) as usize] = -1.;
+        values[512] = 2000.;
+        // Steps of the reduction as the fragment shader of `reduction` does them.
+        let (mut source, mut stride) = (values, width);
+        let mut size = (width, height);
+        let mut sizes = vec![];
+        loop {
+            sizes.push(size);
+            let split = sizes.len() > 1;
+            let cells = reduced_size(size);
+            let mut target = vec![];
+            for y in 0..cells.1 {
+                for x in 0..cells.0 * 2 {
+                    let maximum = x >= cells.0;
+                    let block = if maximum { x - cells.0 } else { x } * REDUCTION_BLOCK;
+                    let block = (block, y * REDUCTION_BLOCK);
+                    let offset = if split && maximum { size.0 } else { 0 };
+                    let fetch = |x: u32, y: u32| source[(y * stride + x + offset) as usize];
+                    let mut color = fetch(block.0, block.1);
+                    for y in block.1..size.1.min(block.1 + REDUCTION_BLOCK) {
+                        for x in block.0..size.0.min(block.0 + REDUCTION_BLOCK) {
+                            let v = fetch(x, y);
+                            color = if maximum { color.max(v) } else { color.min(v) };
+                        }
+                    }
+                    target.push(color);
+                }
+            }
+            source = target;
+            stride = cells.0 * 2;
+            if cells == (1, 1) {
+                break;
+            }
+            size = cells;
+        }
+        assert_eq!(sizes, vec![(513, 7), (65, 1), (9, 1), (2, 1)]);
+        assert_eq!(source, vec![-1., 2000.]);
+    }
+
+    #[test]
+    fn directional_blur_has_single_stage() {
+        let mut g = Generator::new();
+        let n = g.add(Noise::new(0, 2, 2), ());
+        let b = g.add(Blur::new(0.01, 0., BlurType::Directional), ());
+        g.connect(port(n, 0), port(b, 0));
+        let schedule = schedule(&g.dag, port(b, 0), false).unwrap();
+        assert_eq!(schedule.passes.len(), 2);
+        assert_eq!(schedule.output().target, Target::Port(port(b, 0)));
+    }
+}
@@ -1,5 +1,6 @@
-use shader::Context;
-use process::{Process, Setting, SettingMut};
+use cpu::{mix, modulo, smoothstep, Sampler, Vec4};
+use shader::{Context, ShaderError};
+use process::{Channel, Descriptor, Process, Setting, SettingError, SettingMut, Value};
 
 #[derive(Clone, Debug)]
 pub struct Blend(Type, Type);
@@ -11,24 +12,38 @@ impl Blend {
 }
 
 impl Process for Blend {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "blend"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
-            "blend" => Blend(&self.0),
-            "alpha" => Blend(&self.1),
-            _ => panic!(),
-        }
+        Ok(match key {
+            "blend" => Choice(&self.0),
+            "alpha" => Choice(&self.1),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
-            "blend" => Blend(&mut self.0),
-            "alpha" => Blend(&mut self.1),
-            _ => panic!(),
-        }
+        Ok(match key {
+            "blend" => Choice(&mut self.0),
+            "alpha" => Choice(&mut self.1),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
-        vec!["blend", "alpha"]
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "blend",
+                Value::Choice("Normal".into()),
+                "How colors of the inputs are blended",
+            ),
+            Descriptor::new(
+                "alpha",
+                Value::Choice("Normal".into()),
+                "How alphas of the inputs are blended",
+            ),
+        ]
     }
     fn max_in(&self) -> u32 {
         2
@@ -36,19 +51,138 @@ impl Process for Blend {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
-        if ctx.input_len() == 0 {
-            return format!("vec4 {} = vec4(0);\n", ctx.output(0));
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        if let (1, Some(input)) = (ctx.input_len(), ctx.first_input()) {
+            return Ok(format!("vec4 {} = {};\n", ctx.output(0)?, input));
         }
-        if ctx.input_len() == 1 {
-            return format!("vec4 {} = {};\n", ctx.output(0), ctx.first_input());
+        if ctx.input_len() < 2 {
+            return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?));
         }
-        let mut result = format!("vec4 {} = vec4(", ctx.output(0));
+        let mut result = format!("vec4 {} = vec4(", ctx.output(0)?);
         result.push_str(&self.0.blend(ctx, "rgb"));
         result.push_str(",\n");
         result.push_str(&self.1.blend(ctx, "a"));
         result.push_str(");\n");
-        result
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        match (sampler.input(0), sampler.input(1)) {
+            (Some(a), Some(b)) => Vec4::new(
+                self.0.apply(a.r, b.r),
+                self.0.apply(a.g, b.g),
+                self.0.apply(a.b, b.b),
+                self.1.apply(a.a, b.a),
+            ),
+            (Some(i), None) | (None, Some(i)) => i,
+            (None, None) => Vec4::splat(0.),
+        }
+    }
+}
+
+/// Packs a channel of each input into red, green, blue and alpha of the output.
+///
+/// Unconnected inputs give 0 for color channels and 1 for alpha.
+#[derive(Clone, Debug)]
+pub struct CombineChannels {
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+    alpha: Channel,
+}
+
+impl CombineChannels {
+    pub fn new(red: Channel, green: Channel, blue: Channel, alpha: Channel) -> Box<Process> {
+        Box::new(CombineChannels {
+            red: red,
+            green: green,
+            blue: blue,
+            alpha: alpha,
+        })
+    }
+
+    fn sources(&self) -> [Channel; 4] {
+        [self.red, self.green, self.blue, self.alpha]
+    }
+}
+
+impl Process for CombineChannels {
+    fn name(&self) -> &'static str {
+        "combine_channels"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "red" => Choice(&self.red),
+            "green" => Choice(&self.green),
+            "blue" => Choice(&self.blue),
+            "alpha" => Choice(&self.alpha),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "red" => Choice(&mut self.red),
+            "green" => Choice(&mut self.green),
+            "blue" => Choice(&mut self.blue),
+            "alpha" => Choice(&mut self.alpha),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "red",
+                Value::Choice("Luminance".into()),
+                "Channel of the first input that becomes red",
+            ),
+            Descriptor::new(
+                "green",
+                Value::Choice("Luminance".into()),
+                "Channel of the second input that becomes green",
+            ),
+            Descriptor::new(
+                "blue",
+                Value::Choice("Luminance".into()),
+                "Channel of the third input that becomes blue",
+            ),
+            Descriptor::new(
+                "alpha",
+                Value::Choice("Luminance".into()),
+                "Channel of the fourth input that becomes alpha",
+            ),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        4
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let channels = self.sources()
+            .iter()
+            .enumerate()
+            .map(|(i, channel)| match ctx.input(i as u32) {
+                Some(input) => channel.shader(&input.to_string()),
+                None if i == 3 => "1.".into(),
+                None => "0.".into(),
+            })
+            .collect::<Vec<_>>();
+        Ok(format!(
+            "vec4 {} = vec4({});\n",
+            ctx.output(0)?,
+            channels.join(", ")
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let sources = self.sources();
+        let channel = |i: usize| match sampler.input(i as u32) {
+            Some(input) => sources[i].value(input),
+            None if i == 3 => 1.,
+            None => 0.,
+        };
+        Vec4::new(channel(0), channel(1), channel(2), channel(3))
     }
 }
 
@@ -73,6 +207,8 @@ custom_derive! {
     }
 }
 
+impl_choice!(Type);
+
 impl Type {
     fn blend(&self, ctx: &mut Context, channels: &str) -> String {
         use self::Type::*;
@@ -129,6 +265,36 @@ impl Type {
             // b => panic!("Blending mode \"{:?}\" has not been implemented.", b),
         }
     }
+
+    fn apply(&self, a: f32, b: f32) -> f32 {
+        use self::Type::*;
+        match *self {
+            Normal => b,
+            Multiply => a * b,
+            Divide => a / b,
+            Add => a + b,
+            Substract => a - b,
+            Difference => (a - b).abs(),
+            Darken => a.min(b),
+            Lighten => a.max(b),
+            Screen => 1. - (1. - a) * (1. - b),
+            Overlay => if a < 0.5 {
+                2. * a * b
+            } else {
+                1. - 2. * (1. - a) * (1. - b)
+            },
+            Hard => if b < 0.5 {
+                2. * a * b
+            } else {
+                1. - 2. * (1. - a) * (1. - b)
+            },
+            Soft => if b < 0.5 {
+                2. * a * b + a * a - 2. * a * a * b
+            } else {
+                2. * a.sqrt() * b - a.sqrt() + 2. * a - 2. * a * b
+            },
+        }
+    }
 }
 
 fn for_each_channel<F: FnMut(char) -> String>(channels: &str, mut fun: F) -> String {
@@ -150,3 +316,258 @@ fn for_each_channel<F: FnMut(char) -> String>(channels: &str, mut fun: F) -> Str
     }
     result
 }
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(Operations), IterVariantNames(OperationNames))]
+    pub enum Operation {
+        Add,
+        Subtract,
+        Multiply,
+        Divide,
+        Min,
+        Max,
+        Pow,
+        Mod,
+        Clamp,
+        Step,
+        Smoothstep,
+        Sin,
+        Cos,
+        Atan2,
+        Lerp,
+    }
+}
+
+impl_choice!(Operation);
+
+impl Operation {
+    /// GLSL expression applying the operation to operands `a`, `b` and `c` of the same type.
+    fn shader(&self, a: &str, b: &str, c: &str) -> String {
+        use self::Operation::*;
+        match *self {
+            Add => format!("{} + {}", a, b),
+            Subtract => format!("{} - {}", a, b),
+            Multiply => format!("{} * {}", a, b),
+            Divide => format!("{} / {}", a, b),
+            Min => format!("min({}, {})", a, b),
+            Max => format!("max({}, {})", a, b),
+            Pow => format!("pow({}, {})", a, b),
+            Mod => format!("mod({}, {})", a, b),
+            Clamp => format!("min(max({}, {}), {})", a, b, c),
+            Step => format!("step({}, {})", b, a),
+            Smoothstep => format!("smoothstep({}, {}, {})", b, c, a),
+            Sin => format!("sin({})", a),
+            Cos => format!("cos({})", a),
+            Atan2 => format!("atan({}, {})", a, b),
+            Lerp => format!("mix({}, {}, {})", a, b, c),
+        }
+    }
+
+    fn apply(&self, a: f32, b: f32, c: f32) -> f32 {
+        use self::Operation::*;
+        match *self {
+            Add => a + b,
+            Subtract => a - b,
+            Multiply => a * b,
+            Divide => a / b,
+            Min => a.min(b),
+            Max => a.max(b),
+            Pow => a.powf(b),
+            Mod => modulo(a, b),
+            Clamp => a.max(b).min(c),
+            Step => if a >= b { 1. } else { 0. },
+            Smoothstep => smoothstep(b, c, a),
+            Sin => a.sin(),
+            Cos => a.cos(),
+            Atan2 => a.atan2(b),
+            Lerp => mix(a, b, c),
+        }
+    }
+}
+
+/// Applies a math operation to the colors of up to three inputs.
+///
+/// Clamp limits the first input between the other two, step and smoothstep use
+/// them as edges, and lerp mixes the first two by the third. Unconnected inputs
+/// are replaced by constants, and alpha is taken from the first input.
+#[derive(Clone, Debug)]
+pub struct Math {
+    operation: Operation,
+    a: f32,
+    b: f32,
+    c: f32,
+}
+
+impl Math {
+    pub fn new(operation: Operation, a: f32, b: f32, c: f32) -> Box<Process> {
+        Box::new(Math {
+            operation: operation,
+            a: a,
+            b: b,
+            c: c,
+        })
+    }
+
+    fn constants(&self) -> [f32; 3] {
+        [self.a, self.b, self.c]
+    }
+}
+
+impl Process for Math {
+    fn name(&self) -> &'static str {
+        "math"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "operation" => Choice(&self.operation),
+            "a" => Float(&self.a),
+            "b" => Float(&self.b),
+            "c" => Float(&self.c),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "operation" => Choice(&mut self.operation),
+            "a" => Float(&mut self.a),
+            "b" => Float(&mut self.b),
+            "c" => Float(&mut self.c),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "operation",
+                Value::Choice("Add".into()),
+                "Operation applied to the inputs",
+            ),
+            Descriptor::new(
+                "a",
+                Value::Float(0.),
+                "Value of the first input when it isn't connected",
+            ).step(0.01),
+            Descriptor::new(
+                "b",
+                Value::Float(0.),
+                "Value of the second input when it isn't connected",
+            ).step(0.01),
+            Descriptor::new(
+                "c",
+                Value::Float(0.),
+                "Value of the third input when it isn't connected",
+            ).step(0.01),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        3
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let operands = self.constants()
+            .iter()
+            .enumerate()
+            .map(|(i, constant)| match ctx.input(i as u32) {
+                Some(input) => format!("{}.rgb", input),
+                None => format!("vec3({:?})", constant),
+            })
+            .collect::<Vec<_>>();
+        let alpha = match ctx.input(0) {
+            Some(input) => format!("{}.a", input),
+            None => "1.".into(),
+        };
+        Ok(format!(
+            "vec4 {} = vec4({}, {});\n",
+            ctx.output(0)?,
+            self.operation.shader(&operands[0], &operands[1], &operands[2]),
+            alpha
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let constants = self.constants();
+        let operands = (0..3)
+            .map(|i| {
+                sampler
+                    .input(i as u32)
+                    .unwrap_or_else(|| Vec4::gray(constants[i]))
+            })
+            .collect::<Vec<_>>();
+        let (a, b, c) = (operands[0], operands[1], operands[2]);
+        let op = |a: f32, b: f32, c: f32| self.operation.apply(a, b, c);
+        Vec4::new(
+            op(a.r, b.r, c.r),
+            op(a.g, b.g, c.g),
+            op(a.b, b.b, c.b),
+            a.a,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Col, Generator};
+    use dag::port;
+    use process::Constant;
+
+    /// Renders math with the given inputs connected and returns the color of the single pixel.
+    fn math(operation: Operation, inputs: &[Option<Col>], constants: [f32; 3]) -> Vec4 {
+        let mut g = Generator::new();
+        let m = g.add(Math::new(operation, constants[0], constants[1], constants[2]), ());
+        for (i, input) in inputs.iter().enumerate() {
+            if let Some(color) = *input {
+                let c = g.add(Constant::new(color), ());
+                g.connect(port(c, 0), port(m, i as u32));
+            }
+        }
+        Vec4::from(g.evaluate(port(m, 0), 1, 1).unwrap().get(0, 0).unwrap())
+    }
+
+    fn apply(operation: Operation, a: f32, b: f32, c: f32) -> f32 {
+        math(operation, &[], [a, b, c]).r
+    }
+
+    fn gray(v: f32) -> Option<Col> {
+        Some(Col::new(v, v, v, 1.))
+    }
+
+    #[test]
+    fn operand_order() {
+        use self::Operation::*;
+        let (a, b) = (0.5f32, 0.25);
+        assert_eq!(math(Step, &[gray(a), gray(b)], [0.; 3]).r, 1.);
+        assert_eq!(math(Step, &[gray(b), gray(a)], [0.; 3]).r, 0.);
+        assert_eq!(apply(Smoothstep, 0.25, 0., 1.), 0.15625);
+        assert!((apply(Mod, 0.7, 0.25, 0.) - 0.2).abs() < 1e-6);
+        assert!((apply(Atan2, a, b, 0.) - a.atan2(b)).abs() < 1e-6);
+        // GLSL takes the edges of step and smoothstep first, and y before x in atan.
+        assert_eq!(Step.shader("a", "b", "c"), "step(b, a)");
+        assert_eq!(Smoothstep.shader("a", "b", "c"), "smoothstep(b, c, a)");
+        assert_eq!(Mod.shader("a", "b", "c"), "mod(a, b)");
+        assert_eq!(Atan2.shader("a", "b", "c"), "atan(a, b)");
+    }
+
+    #[test]
+    fn negative_mod_matches_glsl() {
+        for &(x, y) in &[(-0.25f32, 1.), (0.25, -1.), (-0.75, -0.5), (-1.5, 0.5)] {
+            let glsl = x - y * (x / y).floor();
+            assert_eq!(apply(Operation::Mod, x, y, 0.), glsl, "mod({}, {})", x, y);
+        }
+        assert_eq!(apply(Operation::Mod, -0.25, 1., 0.), 0.75);
+        assert_eq!(apply(Operation::Mod, 0.25, -1., 0.), -0.75);
+    }
+
+    #[test]
+    fn unconnected_inputs_use_constants() {
+        let lerp = |inputs: &[Option<Col>]| math(Operation::Lerp, inputs, [0.2, 0.6, 0.5]);
+        assert_eq!(lerp(&[]), Vec4::new(0.4, 0.4, 0.4, 1.));
+        assert_eq!(lerp(&[None, gray(1.)]), Vec4::new(0.6, 0.6, 0.6, 1.));
+        let first = Some(Col::new(1., 0., 0.6, 0.5));
+        assert_eq!(lerp(&[first, None, gray(0.)]), Vec4::new(1., 0., 0.6, 0.5));
+    }
+}
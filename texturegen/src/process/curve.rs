@@ -0,0 +1,132 @@
+//! Monotone cubic splines through control points, shared by processes remapping values.
+//!
+//! Tangents are limited as described by Fritsch and Carlson so that the curve
+//! doesn't overshoot between points, which would push values out of range.
+
+use std::cmp::Ordering;
+
+use cpu::Vec2;
+
+/// Cubic polynomial from a control point to the next one.
+struct Segment {
+    start: f32,
+    width: f32,
+    coefficients: [f32; 4],
+}
+
+impl Segment {
+    /// Value of the polynomial at `x`, which is between the two points.
+    fn value(&self, x: f32) -> f32 {
+        let t = (x - self.start) / self.width;
+        let c = self.coefficients;
+        c[0] + t * (c[1] + t * (c[2] + t * c[3]))
+    }
+
+    fn shader(&self, x: &str) -> String {
+        let t = format!("(({} - {:?}) / {:?})", x, self.start, self.width);
+        let c = self.coefficients;
+        format!(
+            "{:?} + {t} * ({:?} + {t} * ({:?} + {t} * {:?}))",
+            c[0],
+            c[1],
+            c[2],
+            c[3],
+            t = t
+        )
+    }
+}
+
+/// Points ordered by x, leaving out ones with the same x as the previous point.
+fn sorted(points: &[Vec2]) -> Vec<Vec2> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+    points.dedup_by(|b, a| b.x <= a.x);
+    points
+}
+
+fn segments(points: &[Vec2]) -> Vec<Segment> {
+    let slopes = points
+        .windows(2)
+        .map(|p| (p[1].y - p[0].y) / (p[1].x - p[0].x))
+        .collect::<Vec<_>>();
+    if slopes.is_empty() {
+        return vec![];
+    }
+    let mut tangents = vec![slopes[0]];
+    for s in slopes.windows(2) {
+        tangents.push(if s[0] * s[1] <= 0. {
+            0.
+        } else {
+            (s[0] + s[1]) / 2.
+        });
+    }
+    tangents.push(slopes[slopes.len() - 1]);
+    for (i, &s) in slopes.iter().enumerate() {
+        if s == 0. {
+            tangents[i] = 0.;
+            tangents[i + 1] = 0.;
+            continue;
+        }
+        let (a, b) = (tangents[i] / s, tangents[i + 1] / s);
+        let length = (a * a + b * b).sqrt();
+        if length > 3. {
+            tangents[i] = 3. / length * a * s;
+            tangents[i + 1] = 3. / length * b * s;
+        }
+    }
+    points
+        .windows(2)
+        .zip(tangents.windows(2))
+        .map(|(p, m)| {
+            let width = p[1].x - p[0].x;
+            let (m0, m1) = (m[0] * width, m[1] * width);
+            let dy = p[1].y - p[0].y;
+            Segment {
+                start: p[0].x,
+                width: width,
+                coefficients: [p[0].y, m0, 3. * dy - 2. * m0 - m1, m0 + m1 - 2. * dy],
+            }
+        })
+        .collect()
+}
+
+/// Value of the curve at `x`, which is that of the first or last point outside of the points.
+pub fn curve(points: &[Vec2], x: f32) -> f32 {
+    let points = sorted(points);
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => return x,
+    };
+    if x <= first.x {
+        return first.y;
+    }
+    if x >= last.x {
+        return last.y;
+    }
+    segments(&points)
+        .into_iter()
+        .take_while(|s| s.start < x)
+        .last()
+        .map(|s| s.value(x))
+        .unwrap_or(first.y)
+}
+
+/// GLSL expression for the value of the curve at `x`, which should be a variable.
+pub fn curve_shader(points: &[Vec2], x: &str) -> String {
+    let points = sorted(points);
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => return x.into(),
+    };
+    let mut result = format!("{:?}", last.y);
+    for s in segments(&points).iter().rev() {
+        result = format!(
+            "({} < {:?} ? {} : {})",
+            x,
+            s.start + s.width,
+            s.shader(x),
+            result
+        );
+    }
+    format!("({} <= {:?} ? {:?} : {})", x, first.x, first.y, result)
+}
@@ -1,6 +1,11 @@
+use std::f32::consts;
+
 use Col;
-use shader::{col, Context};
-use process::{Process, Setting, SettingMut};
+use cpu::{box_sdf, clamp, fract, hash3, iqnoise, iqnoise_tiled, modulo, perlin_noise, smoothstep,
+          snoise, star_sdf, value_noise, worley_noise, Sampler, Vec2, Vec4};
+use shader::{col, Context, ShaderError};
+use process::{Descriptor, Process, Setting, SettingError, SettingMut, Stop, Value};
+use process::ramp::{ramp, ramp_shader, Interpolation};
 
 #[derive(Clone, Debug)]
 pub struct Constant {
@@ -14,22 +19,27 @@ impl Constant {
 }
 
 impl Process for Constant {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "constant"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
+        Ok(match key {
             "color" => Color(&self.color),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
+        Ok(match key {
             "color" => Color(&mut self.color),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
-        vec!["color"]
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("color", Value::Color(Col::new(1., 1., 1., 1.)), "Color to fill with"),
+        ]
     }
     fn max_in(&self) -> u32 {
         0
@@ -37,8 +47,11 @@ impl Process for Constant {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
-        format!("vec4 {} = {};\n", ctx.output(0), col(self.color))
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        Ok(format!("vec4 {} = {};\n", ctx.output(0)?, col(self.color)))
+    }
+    fn evaluate(&self, _: &Sampler, _: u32) -> Vec4 {
+        self.color.into()
     }
 }
 
@@ -62,28 +75,48 @@ impl Stripes {
 }
 
 impl Process for Stripes {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "stripes"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
+        Ok(match key {
             "horizontal" => Integer(&self.hor),
             "vertical" => Integer(&self.ver),
             "even color" => Color(&self.even_col),
             "odd color" => Color(&self.odd_col),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
+        Ok(match key {
             "horizontal" => Integer(&mut self.hor),
             "vertical" => Integer(&mut self.ver),
             "even color" => Color(&mut self.even_col),
             "odd color" => Color(&mut self.odd_col),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
-        vec!["horizontal", "vertical", "even color", "odd color"]
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("horizontal", Value::Integer(1), "Number of horizontal stripes")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("vertical", Value::Integer(4), "Number of vertical stripes")
+                .min(1.)
+                .step(1.),
+            Descriptor::new(
+                "even color",
+                Value::Color(Col::new(1., 1., 1., 1.)),
+                "Color of even stripes",
+            ),
+            Descriptor::new(
+                "odd color",
+                Value::Color(Col::new(0., 0., 0., 1.)),
+                "Color of odd stripes",
+            ),
+        ]
     }
     fn max_in(&self) -> u32 {
         0
@@ -91,23 +124,34 @@ impl Process for Stripes {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
         let mut result = String::new();
-        let hor = 1. / self.hor as f64;
-        let ver = 1. / self.ver as f64;
-        result.push_str(&format!("vec4 {};\n", ctx.output(0)));
+        let hor = 1. / even(self.hor, ctx.tiling()) as f64;
+        let ver = 1. / even(self.ver, ctx.tiling()) as f64;
+        result.push_str(&format!("vec4 {};\n", ctx.output(0)?));
         result.push_str(&format!(
-            "if(mod(v_tex_coords.x, {}) < {} != mod(v_tex_coords.y, {}) < {}) {{\n",
+            "if(mod({c}.x, {}) < {} != mod({c}.y, {}) < {}) {{\n",
             2. * ver,
             ver,
             2. * hor,
-            hor
+            hor,
+            c = ctx.coords()
         ));
-        result.push_str(&format!("{} = {};\n", ctx.output(0), col(self.odd_col)));
+        result.push_str(&format!("{} = {};\n", ctx.output(0)?, col(self.odd_col)));
         result.push_str("} else {\n");
-        result.push_str(&format!("{} = {};\n", ctx.output(0), col(self.even_col)));
+        result.push_str(&format!("{} = {};\n", ctx.output(0)?, col(self.even_col)));
         result.push_str("}\n");
-        result
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let hor = 1. / even(self.hor, sampler.tiling()) as f32;
+        let ver = 1. / even(self.ver, sampler.tiling()) as f32;
+        let coords = sampler.coords();
+        if (modulo(coords.x, 2. * ver) < ver) != (modulo(coords.y, 2. * hor) < hor) {
+            self.odd_col.into()
+        } else {
+            self.even_col.into()
+        }
     }
 }
 
@@ -133,30 +177,55 @@ impl VoronoiNoise {
 }
 
 impl Process for VoronoiNoise {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "voronoi_noise"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
+        Ok(match key {
             "horizontal" => Integer(&self.hor),
             "vertical" => Integer(&self.ver),
             "seed" => Integer(&self.seed),
             "grid" => Float(&self.grid),
             "control" => Float(&self.control),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
+        Ok(match key {
             "horizontal" => Integer(&mut self.hor),
             "vertical" => Integer(&mut self.ver),
             "seed" => Integer(&mut self.seed),
             "grid" => Float(&mut self.grid),
             "control" => Float(&mut self.control),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
-        vec!["seed", "horizontal", "vertical", "grid", "control"]
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("seed", Value::Integer(0), "Seed for the random cell points").step(1.),
+            Descriptor::new("horizontal", Value::Integer(10), "Number of cells horizontally")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("vertical", Value::Integer(10), "Number of cells vertically")
+                .min(1.)
+                .step(1.),
+            Descriptor::new(
+                "grid",
+                Value::Float(1.),
+                "How far cell points are jittered from a regular grid",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+            Descriptor::new(
+                "control",
+                Value::Float(1.),
+                "Blends from voronoi cells (0) to smooth noise (1)",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+        ]
     }
     fn max_in(&self) -> u32 {
         0
@@ -164,21 +233,53 @@ impl Process for VoronoiNoise {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
         let mut result = String::new();
         let temp = ctx.temporary();
         let hor = 1. / self.hor as f32;
         let ver = 1. / self.ver as f32;
-        result.push_str(&format!(
-            "float {} = iqnoise(v_tex_coords / vec2({}, {}), {}, {});\n",
-            temp, hor, ver, self.grid, self.control
-        ));
+        if ctx.tiling() {
+            result.push_str(&format!(
+                "float {} = iqnoise_tiled({} / vec2({}, {}), {}, {}, vec2({}, {}), {}u);\n",
+                temp,
+                ctx.coords(),
+                hor,
+                ver,
+                self.grid,
+                self.control,
+                self.hor,
+                self.ver,
+                self.seed
+            ));
+        } else {
+            result.push_str(&format!(
+                "float {} = iqnoise({} / vec2({}, {}), {}, {}, {}u);\n",
+                temp,
+                ctx.coords(),
+                hor,
+                ver,
+                self.grid,
+                self.control,
+                self.seed
+            ));
+        }
         result.push_str(&format!(
             "vec4 {} = vec4({c}, {c}, {c}, 1.);\n",
-            ctx.output(0),
+            ctx.output(0)?,
             c = temp
         ));
-        result
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let hor = 1. / self.hor as f32;
+        let ver = 1. / self.ver as f32;
+        let x = sampler.coords() / Vec2::new(hor, ver);
+        if sampler.tiling() {
+            let period = Vec2::new(self.hor as f32, self.ver as f32);
+            Vec4::gray(iqnoise_tiled(x, self.grid, self.control, period, self.seed))
+        } else {
+            Vec4::gray(iqnoise(x, self.grid, self.control, self.seed))
+        }
     }
 }
 
@@ -200,26 +301,37 @@ impl Noise {
 }
 
 impl Process for Noise {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "noise"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
+        Ok(match key {
             "horizontal" => Integer(&self.hor),
             "vertical" => Integer(&self.ver),
             "seed" => Integer(&self.seed),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
+        Ok(match key {
             "horizontal" => Integer(&mut self.hor),
             "vertical" => Integer(&mut self.ver),
             "seed" => Integer(&mut self.seed),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
-        vec!["seed", "horizontal", "vertical"]
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("seed", Value::Integer(0), "Seed for the noise").step(1.),
+            Descriptor::new("horizontal", Value::Integer(2), "Horizontal frequency")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("vertical", Value::Integer(2), "Vertical frequency")
+                .min(1.)
+                .step(1.),
+        ]
     }
     fn max_in(&self) -> u32 {
         0
@@ -227,20 +339,1172 @@ impl Process for Noise {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
         let mut result = String::new();
         let temp = ctx.temporary();
-        let hor = 1. / self.hor as f32;
-        let ver = 1. / self.ver as f32;
+        let ver = even(self.ver, ctx.tiling());
+        let period = period(self.hor, ver, ctx.tiling());
         result.push_str(&format!(
-            "float {} = snoise({}, v_tex_coords / vec2({}, {}));\n",
-            temp, self.seed, hor, ver
+            "float {} = snoise({}u, {} * vec2({}, {}), vec2({:?}, {:?}));\n",
+            temp,
+            self.seed,
+            ctx.coords(),
+            self.hor,
+            ver,
+            period.x,
+            period.y
         ));
         result.push_str(&format!(
             "vec4 {} = vec4({c}, {c}, {c}, 1.);\n",
-            ctx.output(0),
+            ctx.output(0)?,
             c = temp
         ));
-        result
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let ver = even(self.ver, sampler.tiling());
+        let period = period(self.hor, ver, sampler.tiling());
+        let scale = Vec2::new(self.hor as f32, ver as f32);
+        Vec4::gray(snoise(self.seed, sampler.coords() * scale, period))
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(FractalTypes), IterVariantNames(FractalTypeNames))]
+    pub enum FractalType {
+        Fbm,
+        Turbulence,
+        Ridged,
+    }
+}
+
+impl_choice!(FractalType);
+
+/// Sum of simplex noise octaves with increasing frequency and decreasing amplitude.
+#[derive(Clone, Debug)]
+pub struct FractalNoise {
+    ver: u32,
+    hor: u32,
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+    ftype: FractalType,
+}
+
+impl FractalNoise {
+    pub fn new(
+        seed: u32,
+        ver: u32,
+        hor: u32,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+        ftype: FractalType,
+    ) -> Box<Process> {
+        Box::new(FractalNoise {
+            seed: seed,
+            ver: ver,
+            hor: hor,
+            octaves: octaves,
+            lacunarity: lacunarity,
+            gain: gain,
+            ftype: ftype,
+        })
+    }
+
+    /// Lacunarity, which has to be an integer for the octaves to wrap when tiling.
+    fn lacunarity(&self, tiling: bool) -> f32 {
+        if tiling {
+            self.lacunarity.round().max(1.)
+        } else {
+            self.lacunarity
+        }
+    }
+}
+
+impl Process for FractalNoise {
+    fn name(&self) -> &'static str {
+        "fractal_noise"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "horizontal" => Integer(&self.hor),
+            "vertical" => Integer(&self.ver),
+            "seed" => Integer(&self.seed),
+            "octaves" => Integer(&self.octaves),
+            "lacunarity" => Float(&self.lacunarity),
+            "gain" => Float(&self.gain),
+            "type" => Choice(&self.ftype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "horizontal" => Integer(&mut self.hor),
+            "vertical" => Integer(&mut self.ver),
+            "seed" => Integer(&mut self.seed),
+            "octaves" => Integer(&mut self.octaves),
+            "lacunarity" => Float(&mut self.lacunarity),
+            "gain" => Float(&mut self.gain),
+            "type" => Choice(&mut self.ftype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("seed", Value::Integer(0), "Seed for the noise").step(1.),
+            Descriptor::new("horizontal", Value::Integer(2), "Horizontal frequency")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("vertical", Value::Integer(2), "Vertical frequency")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("octaves", Value::Integer(5), "Amount of noise layers")
+                .min(1.)
+                .max(16.)
+                .step(1.),
+            Descriptor::new(
+                "lacunarity",
+                Value::Float(2.),
+                "Frequency multiplier between octaves, rounded to an integer when tiling",
+            ).min(1.)
+                .step(0.1),
+            Descriptor::new(
+                "gain",
+                Value::Float(0.5),
+                "Amplitude multiplier between octaves",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+            Descriptor::new(
+                "type",
+                Value::Choice("Fbm".into()),
+                "How octaves are combined",
+            ),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        0
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        use self::FractalType::*;
+        let mut result = String::new();
+        let sum = ctx.temporary();
+        let total = ctx.temporary();
+        let amplitude = ctx.temporary();
+        let weight = ctx.temporary();
+        let pos = ctx.temporary();
+        let period = ctx.temporary();
+        let n = ctx.temporary();
+        let ver = even(self.ver, ctx.tiling());
+        result.push_str(&format!("float {} = 0.;\n", sum));
+        result.push_str(&format!("float {} = 0.;\n", total));
+        result.push_str(&format!("float {} = 1.;\n", amplitude));
+        result.push_str(&format!("float {} = 1.;\n", weight));
+        result.push_str(&format!(
+            "vec2 {} = vec2({}, {});\n",
+            period, self.hor, ver
+        ));
+        result.push_str(&format!("vec2 {} = {} * {};\n", pos, ctx.coords(), period));
+        result.push_str(&format!(
+            "for(int i = 0; i < {}; i++) {{\n",
+            self.octaves
+        ));
+        // Scale of the octave only wraps the noise when tiling.
+        let wrap = if ctx.tiling() {
+            period.to_string()
+        } else {
+            "vec2(0)".into()
+        };
+        result.push_str(&format!(
+            "float {} = snoise({}u + uint(i), {}, {}) * 2. - 1.;\n",
+            n, self.seed, pos, wrap
+        ));
+        match self.ftype {
+            Fbm => {}
+            Turbulence => result.push_str(&format!("{n} = abs({n});\n", n = n)),
+            Ridged => {
+                result.push_str(&format!("{n} = 1. - abs({n});\n", n = n));
+                result.push_str(&format!("{n} *= {n} * {};\n", weight, n = n));
+                result.push_str(&format!("{} = clamp({} * 2., 0., 1.);\n", weight, n));
+            }
+        }
+        result.push_str(&format!("{} += {} * {};\n", sum, n, amplitude));
+        result.push_str(&format!("{} += {};\n", total, amplitude));
+        result.push_str(&format!("{} *= {:?};\n", amplitude, self.gain));
+        result.push_str(&format!("{} *= {:?};\n", pos, self.lacunarity(ctx.tiling())));
+        result.push_str(&format!("{} *= {:?};\n", period, self.lacunarity(ctx.tiling())));
+        result.push_str("}\n");
+        let value = match self.ftype {
+            Fbm => format!("{} / {} * 0.5 + 0.5", sum, total),
+            Turbulence | Ridged => format!("{} / {}", sum, total),
+        };
+        result.push_str(&format!(
+            "vec4 {} = vec4(vec3({}), 1.);\n",
+            ctx.output(0)?,
+            value
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        use self::FractalType::*;
+        let lacunarity = self.lacunarity(sampler.tiling());
+        let mut period = Vec2::new(self.hor as f32, even(self.ver, sampler.tiling()) as f32);
+        let mut pos = sampler.coords() * period;
+        let (mut sum, mut total, mut amplitude, mut weight) = (0., 0., 1., 1.);
+        for i in 0..self.octaves {
+            let seed = self.seed.wrapping_add(i);
+            let wrap = if sampler.tiling() {
+                period
+            } else {
+                Vec2::splat(0.)
+            };
+            let mut n = snoise(seed, pos, wrap) * 2. - 1.;
+            match self.ftype {
+                Fbm => {}
+                Turbulence => n = n.abs(),
+                Ridged => {
+                    n = 1. - n.abs();
+                    n = n * n * weight;
+                    weight = clamp(n * 2., 0., 1.);
+                }
+            }
+            sum += n * amplitude;
+            total += amplitude;
+            amplitude *= self.gain;
+            pos = pos * lacunarity;
+            period = period * lacunarity;
+        }
+        Vec4::gray(match self.ftype {
+            Fbm => sum / total * 0.5 + 0.5,
+            Turbulence | Ridged => sum / total,
+        })
+    }
+}
+
+/// Classic gradient noise.
+#[derive(Clone, Debug)]
+pub struct PerlinNoise {
+    ver: u32,
+    hor: u32,
+    seed: u32,
+}
+
+impl PerlinNoise {
+    pub fn new(seed: u32, ver: u32, hor: u32) -> Box<Process> {
+        Box::new(PerlinNoise {
+            seed: seed,
+            ver: ver,
+            hor: hor,
+        })
+    }
+}
+
+impl Process for PerlinNoise {
+    fn name(&self) -> &'static str {
+        "perlin_noise"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "horizontal" => Integer(&self.hor),
+            "vertical" => Integer(&self.ver),
+            "seed" => Integer(&self.seed),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "horizontal" => Integer(&mut self.hor),
+            "vertical" => Integer(&mut self.ver),
+            "seed" => Integer(&mut self.seed),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("seed", Value::Integer(0), "Seed for the noise").step(1.),
+            Descriptor::new("horizontal", Value::Integer(4), "Horizontal frequency")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("vertical", Value::Integer(4), "Vertical frequency")
+                .min(1.)
+                .step(1.),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        0
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let period = period(self.hor, self.ver, ctx.tiling());
+        Ok(format!(
+            "vec4 {} = vec4(vec3(perlin_noise({} * vec2({}, {}), vec2({:?}, {:?}), {}u)), 1.);\n",
+            ctx.output(0)?,
+            ctx.coords(),
+            self.hor,
+            self.ver,
+            period.x,
+            period.y,
+            self.seed
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let scale = Vec2::new(self.hor as f32, self.ver as f32);
+        let period = period(self.hor, self.ver, sampler.tiling());
+        Vec4::gray(perlin_noise(sampler.coords() * scale, period, self.seed))
+    }
+}
+
+/// Smoothly interpolated random values on a grid.
+#[derive(Clone, Debug)]
+pub struct ValueNoise {
+    ver: u32,
+    hor: u32,
+    seed: u32,
+}
+
+impl ValueNoise {
+    pub fn new(seed: u32, ver: u32, hor: u32) -> Box<Process> {
+        Box::new(ValueNoise {
+            seed: seed,
+            ver: ver,
+            hor: hor,
+        })
+    }
+}
+
+impl Process for ValueNoise {
+    fn name(&self) -> &'static str {
+        "value_noise"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "horizontal" => Integer(&self.hor),
+            "vertical" => Integer(&self.ver),
+            "seed" => Integer(&self.seed),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "horizontal" => Integer(&mut self.hor),
+            "vertical" => Integer(&mut self.ver),
+            "seed" => Integer(&mut self.seed),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("seed", Value::Integer(0), "Seed for the noise").step(1.),
+            Descriptor::new("horizontal", Value::Integer(4), "Horizontal frequency")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("vertical", Value::Integer(4), "Vertical frequency")
+                .min(1.)
+                .step(1.),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        0
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let period = period(self.hor, self.ver, ctx.tiling());
+        Ok(format!(
+            "vec4 {} = vec4(vec3(value_noise({} * vec2({}, {}), vec2({:?}, {:?}), {}u)), 1.);\n",
+            ctx.output(0)?,
+            ctx.coords(),
+            self.hor,
+            self.ver,
+            period.x,
+            period.y,
+            self.seed
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let scale = Vec2::new(self.hor as f32, self.ver as f32);
+        let period = period(self.hor, self.ver, sampler.tiling());
+        Vec4::gray(value_noise(sampler.coords() * scale, period, self.seed))
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(WorleyMetrics), IterVariantNames(WorleyMetricNames))]
+    pub enum WorleyMetric {
+        Euclidean,
+        Manhattan,
+        Chebyshev,
+    }
+}
+
+impl_choice!(WorleyMetric);
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(WorleyModes), IterVariantNames(WorleyModeNames))]
+    pub enum WorleyMode {
+        F1,
+        F2,
+        F2MinusF1,
+        CellId,
+    }
+}
+
+impl_choice!(WorleyMode);
+
+impl WorleyMode {
+    /// Picks the output from distances to the two closest points and the cell value.
+    fn select(&self, f: [f32; 3]) -> f32 {
+        use self::WorleyMode::*;
+        match *self {
+            F1 => f[0],
+            F2 => f[1],
+            F2MinusF1 => f[1] - f[0],
+            CellId => f[2],
+        }
+    }
+
+    fn select_shader(&self, f: &str) -> String {
+        use self::WorleyMode::*;
+        match *self {
+            F1 => format!("{}.x", f),
+            F2 => format!("{}.y", f),
+            F2MinusF1 => format!("{f}.y - {f}.x", f = f),
+            CellId => format!("{}.z", f),
+        }
+    }
+}
+
+/// Cellular noise from distances to randomly placed points, one per grid cell.
+#[derive(Clone, Debug)]
+pub struct WorleyNoise {
+    ver: u32,
+    hor: u32,
+    seed: u32,
+    metric: WorleyMetric,
+    mode: WorleyMode,
+}
+
+impl WorleyNoise {
+    pub fn new(
+        seed: u32,
+        ver: u32,
+        hor: u32,
+        metric: WorleyMetric,
+        mode: WorleyMode,
+    ) -> Box<Process> {
+        Box::new(WorleyNoise {
+            seed: seed,
+            ver: ver,
+            hor: hor,
+            metric: metric,
+            mode: mode,
+        })
+    }
+}
+
+impl Process for WorleyNoise {
+    fn name(&self) -> &'static str {
+        "worley_noise"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "horizontal" => Integer(&self.hor),
+            "vertical" => Integer(&self.ver),
+            "seed" => Integer(&self.seed),
+            "metric" => Choice(&self.metric),
+            "mode" => Choice(&self.mode),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "horizontal" => Integer(&mut self.hor),
+            "vertical" => Integer(&mut self.ver),
+            "seed" => Integer(&mut self.seed),
+            "metric" => Choice(&mut self.metric),
+            "mode" => Choice(&mut self.mode),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("seed", Value::Integer(0), "Seed for the random cell points").step(1.),
+            Descriptor::new("horizontal", Value::Integer(8), "Horizontal cell count")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("vertical", Value::Integer(8), "Vertical cell count")
+                .min(1.)
+                .step(1.),
+            Descriptor::new(
+                "metric",
+                Value::Choice("Euclidean".into()),
+                "How distance to the points is measured",
+            ),
+            Descriptor::new(
+                "mode",
+                Value::Choice("F1".into()),
+                "Distance to the closest or second closest point, their difference or \
+                 random value of the closest cell",
+            ),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        0
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let period = period(self.hor, self.ver, ctx.tiling());
+        let temp = ctx.temporary();
+        let mut result = format!(
+            "vec3 {} = worley_noise({} * vec2({}, {}), vec2({:?}, {:?}), {}u, {});\n",
+            temp,
+            ctx.coords(),
+            self.hor,
+            self.ver,
+            period.x,
+            period.y,
+            self.seed,
+            self.metric as i32
+        );
+        result.push_str(&format!(
+            "vec4 {} = vec4(vec3({}), 1.);\n",
+            ctx.output(0)?,
+            self.mode.select_shader(&temp.to_string())
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let scale = Vec2::new(self.hor as f32, self.ver as f32);
+        let period = period(self.hor, self.ver, sampler.tiling());
+        let f = worley_noise(
+            sampler.coords() * scale,
+            period,
+            self.seed,
+            self.metric as i32,
+        );
+        Vec4::gray(self.mode.select(f))
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(GradientTypes), IterVariantNames(GradientTypeNames))]
+    pub enum GradientType {
+        Linear,
+        Radial,
+        Angular,
+        Diamond,
+    }
+}
+
+impl_choice!(GradientType);
+
+/// Color ramp laid out along a shape centered on the texture.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    gtype: GradientType,
+    angle: f32,
+    stops: Vec<Stop>,
+}
+
+impl Gradient {
+    pub fn new(gtype: GradientType, angle: f32, stops: Vec<Stop>) -> Box<Process> {
+        Box::new(Gradient {
+            gtype: gtype,
+            angle: angle,
+            stops: stops,
+        })
+    }
+
+    /// Unit direction of linear gradients scaled so that the corners are at 0 and 1.
+    fn direction(&self) -> Vec2 {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        Vec2::new(cos, sin) / (cos.abs() + sin.abs())
+    }
+}
+
+impl Process for Gradient {
+    fn name(&self) -> &'static str {
+        "gradient"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "type" => Choice(&self.gtype),
+            "angle" => Float(&self.angle),
+            "stops" => Stops(&self.stops),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "type" => Choice(&mut self.gtype),
+            "angle" => Float(&mut self.angle),
+            "stops" => Stops(&mut self.stops),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "type",
+                Value::Choice("Linear".into()),
+                "Shape along which the colors change",
+            ),
+            Descriptor::new(
+                "angle",
+                Value::Float(0.),
+                "Direction of linear and start of angular gradients in degrees",
+            ).step(15.),
+            Descriptor::new(
+                "stops",
+                Value::Stops(vec![
+                    Stop::new(0., Col::new(0., 0., 0., 1.)),
+                    Stop::new(1., Col::new(1., 1., 1., 1.)),
+                ]),
+                "Colors and their positions from 0 to 1",
+            ).min(0.)
+                .max(1.),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        0
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        use self::GradientType::*;
+        let d = ctx.temporary();
+        let t = ctx.temporary();
+        let position = match self.gtype {
+            Linear => {
+                let dir = self.direction();
+                format!("dot({}, vec2({:?}, {:?})) + 0.5", d, dir.x, dir.y)
+            }
+            Radial => format!("length({}) * 2.", d),
+            Angular => format!(
+                "fract(atan({d}.y, {d}.x) * {:?} - {:?})",
+                0.5 * consts::FRAC_1_PI,
+                self.angle / 360.,
+                d = d
+            ),
+            Diamond => format!("(abs({d}.x) + abs({d}.y)) * 2.", d = d),
+        };
+        Ok(format!(
+            "vec2 {} = {} - 0.5;\nfloat {} = {};\nvec4 {} = {};\n",
+            d,
+            ctx.coords(),
+            t,
+            position,
+            ctx.output(0)?,
+            ramp_shader(&self.stops, &t.to_string(), Interpolation::Linear)
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        use self::GradientType::*;
+        let d = sampler.coords() - Vec2::splat(0.5);
+        let t = match self.gtype {
+            Linear => d.dot(self.direction()) + 0.5,
+            Radial => d.length() * 2.,
+            Angular => fract(d.y.atan2(d.x) * 0.5 * consts::FRAC_1_PI - self.angle / 360.),
+            Diamond => (d.x.abs() + d.y.abs()) * 2.,
+        };
+        ramp(&self.stops, t, Interpolation::Linear)
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(ShapeTypes), IterVariantNames(ShapeTypeNames))]
+    pub enum ShapeType {
+        Circle,
+        RoundedRectangle,
+        Polygon,
+        Star,
+        Ring,
+    }
+}
+
+impl_choice!(ShapeType);
+
+/// Antialiased mask of a shape, computed from its signed distance field.
+#[derive(Clone, Debug)]
+pub struct Shape {
+    stype: ShapeType,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    rotation: f32,
+    sides: u32,
+    roundness: f32,
+    inner: f32,
+    feather: f32,
+    outline: bool,
+    thickness: f32,
+}
+
+impl Shape {
+    pub fn new(stype: ShapeType) -> Box<Process> {
+        Box::new(Shape {
+            stype: stype,
+            x: 0.5,
+            y: 0.5,
+            width: 0.5,
+            height: 0.5,
+            rotation: 0.,
+            sides: 5,
+            roundness: 0.25,
+            inner: 0.5,
+            feather: 0.,
+            outline: false,
+            thickness: 0.02,
+        })
+    }
+
+    fn half_size(&self) -> Vec2 {
+        Vec2::new(self.width, self.height).map(|s| (s * 0.5).max(1e-6))
+    }
+
+    fn sides(&self) -> f32 {
+        self.sides.max(3) as f32
+    }
+
+    /// Signed distance from the shape in texture coordinates relative to its center.
+    ///
+    /// Shapes other than rectangles are scaled from the unit circle to the size,
+    /// so their distance is only exact when width and height are equal.
+    fn distance(&self, p: Vec2) -> f32 {
+        use self::ShapeType::*;
+        let half = self.half_size();
+        let scale = half.x.min(half.y);
+        let q = p / half;
+        match self.stype {
+            Circle => (q.length() - 1.) * scale,
+            RoundedRectangle => box_sdf(p, half, clamp(self.roundness, 0., 1.) * scale),
+            Polygon => star_sdf(q, self.sides(), (consts::PI / self.sides()).cos()) * scale,
+            Star => star_sdf(q, self.sides(), self.inner) * scale,
+            Ring => {
+                let mid = (1. + self.inner) * 0.5;
+                let half_width = (1. - self.inner) * 0.5;
+                ((q.length() - mid).abs() - half_width) * scale
+            }
+        }
+    }
+
+    fn distance_shader(&self, p: &str) -> String {
+        use self::ShapeType::*;
+        let half = self.half_size();
+        let scale = half.x.min(half.y);
+        let q = format!("{} / vec2({:?}, {:?})", p, half.x, half.y);
+        match self.stype {
+            Circle => format!("(length({}) - 1.) * {:?}", q, scale),
+            RoundedRectangle => format!(
+                "box_sdf({}, vec2({:?}, {:?}), {:?})",
+                p,
+                half.x,
+                half.y,
+                clamp(self.roundness, 0., 1.) * scale
+            ),
+            Polygon => format!(
+                "star_sdf({}, {:?}, {:?}) * {:?}",
+                q,
+                self.sides(),
+                (consts::PI / self.sides()).cos(),
+                scale
+            ),
+            Star => format!(
+                "star_sdf({}, {:?}, {:?}) * {:?}",
+                q,
+                self.sides(),
+                self.inner,
+                scale
+            ),
+            Ring => format!(
+                "(abs(length({}) - {:?}) - {:?}) * {:?}",
+                q,
+                (1. + self.inner) * 0.5,
+                (1. - self.inner) * 0.5,
+                scale
+            ),
+        }
+    }
+}
+
+impl Process for Shape {
+    fn name(&self) -> &'static str {
+        "shape"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "type" => Choice(&self.stype),
+            "x" => Float(&self.x),
+            "y" => Float(&self.y),
+            "width" => Float(&self.width),
+            "height" => Float(&self.height),
+            "rotation" => Float(&self.rotation),
+            "sides" => Integer(&self.sides),
+            "roundness" => Float(&self.roundness),
+            "inner" => Float(&self.inner),
+            "feather" => Float(&self.feather),
+            "outline" => Boolean(&self.outline),
+            "thickness" => Float(&self.thickness),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "type" => Choice(&mut self.stype),
+            "x" => Float(&mut self.x),
+            "y" => Float(&mut self.y),
+            "width" => Float(&mut self.width),
+            "height" => Float(&mut self.height),
+            "rotation" => Float(&mut self.rotation),
+            "sides" => Integer(&mut self.sides),
+            "roundness" => Float(&mut self.roundness),
+            "inner" => Float(&mut self.inner),
+            "feather" => Float(&mut self.feather),
+            "outline" => Boolean(&mut self.outline),
+            "thickness" => Float(&mut self.thickness),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("type", Value::Choice("Circle".into()), "Shape to draw"),
+            Descriptor::new("x", Value::Float(0.5), "Horizontal position of the center").step(0.05),
+            Descriptor::new("y", Value::Float(0.5), "Vertical position of the center").step(0.05),
+            Descriptor::new("width", Value::Float(0.5), "Width of the shape")
+                .min(0.)
+                .step(0.05),
+            Descriptor::new("height", Value::Float(0.5), "Height of the shape")
+                .min(0.)
+                .step(0.05),
+            Descriptor::new("rotation", Value::Float(0.), "Rotation in degrees").step(15.),
+            Descriptor::new(
+                "sides",
+                Value::Integer(5),
+                "Number of sides of polygons or points of stars",
+            ).min(3.)
+                .step(1.),
+            Descriptor::new(
+                "roundness",
+                Value::Float(0.25),
+                "Corner radius of rounded rectangles relative to their size",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+            Descriptor::new(
+                "inner",
+                Value::Float(0.5),
+                "Inner radius of stars and rings relative to the outer one",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+            Descriptor::new(
+                "feather",
+                Value::Float(0.),
+                "Width of the soft edge in texture coordinates",
+            ).min(0.)
+                .step(0.005),
+            Descriptor::new(
+                "outline",
+                Value::Boolean(false),
+                "Draws only the outline instead of filling the shape",
+            ),
+            Descriptor::new("thickness", Value::Float(0.02), "Width of the outline")
+                .min(0.)
+                .step(0.005),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        0
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let p = ctx.temporary();
+        let d = ctx.temporary();
+        let w = ctx.temporary();
+        let mut result = format!(
+            "vec2 {p} = {} - vec2({:?}, {:?});\n\
+             {p} = vec2({p}.x * {c:?} + {p}.y * {s:?}, {p}.y * {c:?} - {p}.x * {s:?});\n",
+            ctx.coords(),
+            self.x,
+            self.y,
+            p = p,
+            c = cos,
+            s = sin
+        );
+        result.push_str(&format!(
+            "float {} = {};\n",
+            d,
+            self.distance_shader(&p.to_string())
+        ));
+        if self.outline {
+            result.push_str(&format!("{d} = abs({d}) - {:?};\n", self.thickness * 0.5, d = d));
+        }
+        // Edge is smoothed over at least a pixel
+        result.push_str(&format!(
+            "float {w} = max({:?}, max(fwidth({c}).x, fwidth({c}).y)) * 0.5;\n\
+             vec4 {} = vec4(vec3(1. - smoothstep(-{w}, {w}, {})), 1.);\n",
+            self.feather,
+            ctx.output(0)?,
+            d,
+            w = w,
+            c = ctx.coords()
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let p = sampler.coords() - Vec2::new(self.x, self.y);
+        let p = Vec2::new(p.x * cos + p.y * sin, p.y * cos - p.x * sin);
+        let mut d = self.distance(p);
+        if self.outline {
+            d = d.abs() - self.thickness * 0.5;
+        }
+        let width = sampler.fwidth();
+        let w = self.feather.max(width.x.max(width.y)) * 0.5;
+        Vec4::gray(1. - smoothstep(-w, w, d))
+    }
+}
+
+/// Rows of tiles separated by mortar, each row shifted by the offset.
+///
+/// Outputs the tile mask with per-tile brightness variation, a random value
+/// identifying each tile and height rising from the tile edges over the bevel.
+#[derive(Clone, Debug)]
+pub struct Bricks {
+    seed: u32,
+    rows: u32,
+    columns: u32,
+    offset: f32,
+    mortar: f32,
+    bevel: f32,
+    variation: f32,
+}
+
+impl Bricks {
+    pub fn new(
+        seed: u32,
+        rows: u32,
+        columns: u32,
+        offset: f32,
+        mortar: f32,
+        bevel: f32,
+        variation: f32,
+    ) -> Box<Process> {
+        Box::new(Bricks {
+            seed: seed,
+            rows: rows,
+            columns: columns,
+            offset: offset,
+            mortar: mortar,
+            bevel: bevel,
+            variation: variation,
+        })
+    }
+
+    fn size(&self) -> Vec2 {
+        Vec2::new(self.columns.max(1) as f32, self.rows.max(1) as f32)
+    }
+
+    /// Row offset, which is rounded when tiling so that the last row lines up with the first.
+    fn offset(&self, tiling: bool) -> f32 {
+        if tiling {
+            let rows = self.size().y;
+            (self.offset * rows).round() / rows
+        } else {
+            self.offset
+        }
+    }
+}
+
+impl Process for Bricks {
+    fn name(&self) -> &'static str {
+        "bricks"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "seed" => Integer(&self.seed),
+            "rows" => Integer(&self.rows),
+            "columns" => Integer(&self.columns),
+            "offset" => Float(&self.offset),
+            "mortar" => Float(&self.mortar),
+            "bevel" => Float(&self.bevel),
+            "variation" => Float(&self.variation),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "seed" => Integer(&mut self.seed),
+            "rows" => Integer(&mut self.rows),
+            "columns" => Integer(&mut self.columns),
+            "offset" => Float(&mut self.offset),
+            "mortar" => Float(&mut self.mortar),
+            "bevel" => Float(&mut self.bevel),
+            "variation" => Float(&mut self.variation),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("seed", Value::Integer(0), "Seed for the tile values").step(1.),
+            Descriptor::new("rows", Value::Integer(8), "Number of rows")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("columns", Value::Integer(4), "Number of tiles in a row")
+                .min(1.)
+                .step(1.),
+            Descriptor::new(
+                "offset",
+                Value::Float(0.5),
+                "Shift of each row relative to the previous one in tile widths",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+            Descriptor::new(
+                "mortar",
+                Value::Float(0.01),
+                "Width of the gaps between tiles in texture coordinates",
+            ).min(0.)
+                .step(0.005),
+            Descriptor::new(
+                "bevel",
+                Value::Float(0.02),
+                "Distance from the tile edge over which the height rises",
+            ).min(0.)
+                .step(0.005),
+            Descriptor::new(
+                "variation",
+                Value::Float(0.2),
+                "How much brightness of the tiles varies",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        0
+    }
+    fn max_out(&self) -> u32 {
+        3
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let size = self.size();
+        let size = format!("vec2({:?}, {:?})", size.x, size.y);
+        let p = ctx.temporary();
+        let cell = ctx.temporary();
+        let h = ctx.temporary();
+        let e = ctx.temporary();
+        let d = ctx.temporary();
+        let w = ctx.temporary();
+        let mut result = format!(
+            "vec2 {p} = {} * {s};\n\
+             {p}.x += floor({p}.y) * {:?};\n\
+             vec2 {cell} = floor({p});\n\
+             vec3 {h} = hash3(mod({cell}, {s}), {}u);\n\
+             vec2 {e} = min({p} - {cell}, 1. - {p} + {cell}) / {s};\n\
+             float {d} = min({e}.x, {e}.y) - {:?};\n\
+             float {w} = max(fwidth({c}).x, fwidth({c}).y) * 0.5;\n",
+            ctx.coords(),
+            self.offset(ctx.tiling()),
+            self.seed,
+            self.mortar * 0.5,
+            p = p,
+            s = size,
+            cell = cell,
+            h = h,
+            e = e,
+            d = d,
+            w = w,
+            c = ctx.coords()
+        );
+        result.push_str(&format!(
+            "vec4 {} = vec4(vec3(smoothstep(-{w}, {w}, {d}) * (1. - {:?} * {h}.y)), 1.);\n\
+             vec4 {} = vec4(vec3({h}.x), 1.);\n\
+             vec4 {} = vec4(vec3(clamp({d} / {:?}, 0., 1.)), 1.);\n",
+            ctx.output(0)?,
+            self.variation,
+            ctx.output(1)?,
+            ctx.output(2)?,
+            self.bevel.max(1e-6),
+            w = w,
+            d = d,
+            h = h
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, output: u32) -> Vec4 {
+        let size = self.size();
+        let mut p = sampler.coords() * size;
+        p.x += p.y.floor() * self.offset(sampler.tiling());
+        let cell = p.map(f32::floor);
+        let h = hash3(
+            Vec2::new(modulo(cell.x, size.x), modulo(cell.y, size.y)),
+            self.seed,
+        );
+        let f = p - cell;
+        let e = Vec2::new(f.x.min(1. - f.x), f.y.min(1. - f.y)) / size;
+        let d = e.x.min(e.y) - self.mortar * 0.5;
+        match output {
+            0 => {
+                let width = sampler.fwidth();
+                let w = width.x.max(width.y) * 0.5;
+                Vec4::gray(smoothstep(-w, w, d) * (1. - self.variation * h[1]))
+            }
+            1 => Vec4::gray(h[0]),
+            _ => Vec4::gray(clamp(d / self.bevel.max(1e-6), 0., 1.)),
+        }
+    }
+}
+
+/// Period of lattice noise with given frequencies, which is zero when not tiling.
+fn period(hor: u32, ver: u32, tiling: bool) -> Vec2 {
+    if tiling {
+        Vec2::new(hor as f32, ver as f32)
+    } else {
+        Vec2::splat(0.)
+    }
+}
+
+/// Rounds odd count of repetitions above one up to even when tiling, so that alternating patterns
+/// wrap.
+fn even(count: u32, tiling: bool) -> u32 {
+    if tiling && count > 1 {
+        count + count % 2
+    } else {
+        count
     }
 }
@@ -1,15 +1,81 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
 use Col;
-use shader::Context;
+use cpu::{Sampler, Vec2, Vec4};
+use shader::{Context, ShaderError};
+
+/// Implements `Choice` for enums deriving `IterVariants` and `IterVariantNames`.
+macro_rules! impl_choice {
+    ($t:ident) => {
+        impl ::process::Choice for $t {
+            fn variants(&self) -> Vec<&'static str> {
+                $t::iter_variant_names().collect()
+            }
+            fn index(&self) -> usize {
+                $t::iter_variants()
+                    .position(|v| v == *self)
+                    .expect("Enum value wasn't one of its variants")
+            }
+            fn select(&mut self, index: usize) -> bool {
+                if let Some(v) = $t::iter_variants().nth(index) {
+                    *self = v;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
 
 pub mod inputs;
 pub mod combiners;
 pub mod modifiers;
+mod curve;
+mod ramp;
+mod registry;
 
-pub use self::inputs::{Constant, Noise, Stripes, VoronoiNoise};
-pub use self::combiners::Blend;
+pub use self::inputs::{Bricks, Constant, FractalNoise, FractalType, Gradient, GradientType, Noise,
+                       PerlinNoise, Shape, ShapeType, Stripes, ValueNoise, VoronoiNoise,
+                       WorleyMetric, WorleyMode, WorleyNoise};
+pub use self::combiners::{Blend, CombineChannels, Math, Operation};
 pub use self::combiners::Type as BlendType;
-pub use self::modifiers::{EdgeDetect, Invert, Select};
+pub use self::modifiers::{AmbientOcclusion, Blur, BlurType, Channel, ChannelMode, ColorModel,
+                          Curvature, Curves, Derivative, EdgeDetect, GradientMap, HueSaturation,
+                          Invert, Levels, NormalMap, Select, SplitChannels, Transform, Warp,
+                          WarpType};
 pub use self::modifiers::Type as EdgeDetectType;
+pub use self::ramp::Interpolation;
+pub use self::registry::{Category, Entry, Registry};
+
+/// Color at a position of a color ramp, which is usually between 0 and 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {
+    pub position: f32,
+    pub color: Col,
+}
+
+impl Stop {
+    pub fn new(position: f32, color: Col) -> Stop {
+        Stop {
+            position: position,
+            color: color,
+        }
+    }
+}
+
+/// Setting that picks one of named options, such as the variant of an enum.
+pub trait Choice {
+    fn variants(&self) -> Vec<&'static str>;
+    fn index(&self) -> usize;
+    /// Selects the variant at index, returning false if there isn't one.
+    fn select(&mut self, index: usize) -> bool;
+
+    fn variant(&self) -> &'static str {
+        self.variants()[self.index()]
+    }
+}
 
 pub enum Setting<'a> {
     Text(&'a String),
@@ -17,7 +83,9 @@ pub enum Setting<'a> {
     Boolean(&'a bool),
     Float(&'a f32),
     Color(&'a Col),
-    Blend(&'a BlendType),
+    Choice(&'a Choice),
+    Stops(&'a Vec<Stop>),
+    Points(&'a Vec<Vec2>),
 }
 
 pub enum SettingMut<'a> {
@@ -26,30 +94,296 @@ pub enum SettingMut<'a> {
     Boolean(&'a mut bool),
     Float(&'a mut f32),
     Color(&'a mut Col),
-    Blend(&'a mut BlendType),
+    Choice(&'a mut Choice),
+    Stops(&'a mut Vec<Stop>),
+    Points(&'a mut Vec<Vec2>),
+}
+
+/// Owned counterpart of `Setting`, used when settings are stored or restored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Text(String),
+    Integer(u32),
+    Boolean(bool),
+    Float(f32),
+    Color(Col),
+    /// Name of the selected variant.
+    Choice(String),
+    Stops(Vec<Stop>),
+    /// Control points of a curve, which don't have to be ordered.
+    Points(Vec<Vec2>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Text,
+    Integer,
+    Boolean,
+    Float,
+    Color,
+    Choice,
+    Stops,
+    Points,
+}
+
+impl Value {
+    pub fn kind(&self) -> Kind {
+        match *self {
+            Value::Text(_) => Kind::Text,
+            Value::Integer(_) => Kind::Integer,
+            Value::Boolean(_) => Kind::Boolean,
+            Value::Float(_) => Kind::Float,
+            Value::Color(_) => Kind::Color,
+            Value::Choice(_) => Kind::Choice,
+            Value::Stops(_) => Kind::Stops,
+            Value::Points(_) => Kind::Points,
+        }
+    }
+}
+
+/// Describes a setting of a process well enough to edit, validate or store it
+/// without knowing the process.
+///
+/// Bounds and step only apply to numeric settings, except that bounds also
+/// limit the positions of color stops and both coordinates of curve points.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Descriptor {
+    pub name: &'static str,
+    pub default: Value,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub step: Option<f32>,
+    pub description: &'static str,
+}
+
+impl Descriptor {
+    pub fn new(name: &'static str, default: Value, description: &'static str) -> Descriptor {
+        Descriptor {
+            name: name,
+            default: default,
+            min: None,
+            max: None,
+            step: None,
+            description: description,
+        }
+    }
+
+    pub fn min(mut self, min: f32) -> Descriptor {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn max(mut self, max: f32) -> Descriptor {
+        self.max = Some(max);
+        self
+    }
+
+    pub fn step(mut self, step: f32) -> Descriptor {
+        self.step = Some(step);
+        self
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.default.kind()
+    }
+
+    /// Checks that the value is of right kind and within bounds.
+    pub fn validate(&self, value: &Value) -> Result<(), Reason> {
+        if value.kind() != self.kind() {
+            return Err(Reason::WrongKind);
+        }
+        let numbers = match *value {
+            Value::Integer(i) => vec![i as f32],
+            Value::Float(f) => vec![f],
+            Value::Stops(ref s) if s.is_empty() => return Err(Reason::OutOfBounds),
+            Value::Stops(ref s) => s.iter().map(|s| s.position).collect(),
+            Value::Points(ref p) if p.is_empty() => return Err(Reason::OutOfBounds),
+            Value::Points(ref p) => p.iter().flat_map(|p| vec![p.x, p.y]).collect(),
+            _ => return Ok(()),
+        };
+        for number in numbers {
+            if self.min.map(|m| number < m).unwrap_or(false)
+                || self.max.map(|m| number > m).unwrap_or(false)
+            {
+                return Err(Reason::OutOfBounds);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Setting<'a> {
+    pub fn to_value(&self) -> Value {
+        use self::Setting::*;
+        match *self {
+            Text(t) => Value::Text(t.clone()),
+            Integer(i) => Value::Integer(*i),
+            Boolean(b) => Value::Boolean(*b),
+            Float(f) => Value::Float(*f),
+            Color(c) => Value::Color(*c),
+            Choice(c) => Value::Choice(c.variant().into()),
+            Stops(s) => Value::Stops(s.clone()),
+            Points(p) => Value::Points(p.clone()),
+        }
+    }
+}
+
+impl<'a> SettingMut<'a> {
+    /// Overwrites the setting if the value is of the same kind and a valid variant for choices.
+    pub fn set(self, value: Value) -> Result<(), Reason> {
+        use self::SettingMut::*;
+        match (self, value) {
+            (Text(t), Value::Text(v)) => *t = v,
+            (Integer(i), Value::Integer(v)) => *i = v,
+            (Boolean(b), Value::Boolean(v)) => *b = v,
+            (Float(f), Value::Float(v)) => *f = v,
+            (Color(c), Value::Color(v)) => *c = v,
+            (Choice(c), Value::Choice(v)) => {
+                let index = c.variants()
+                    .iter()
+                    .position(|n| *n == v)
+                    .ok_or(Reason::OutOfBounds)?;
+                c.select(index);
+            }
+            (Stops(s), Value::Stops(v)) => *s = v,
+            (Points(p), Value::Points(v)) => *p = v,
+            _ => return Err(Reason::WrongKind),
+        }
+        Ok(())
+    }
 }
 
 impl<'a> ToString for Setting<'a> {
     fn to_string(&self) -> String {
         use self::Setting::*;
         match *self {
-            Text(ref t) => (*t).clone(),
-            Integer(ref i) => format!("{}", i),
-            Boolean(ref b) => format!("{}", if **b { 1 } else { 0 }),
-            Float(ref f) => format!("{}", f),
-            Color(ref c) => format!("{},{},{},{}", c.red, c.green, c.blue, c.alpha),
-            Blend(ref b) => format!("{:?}", b),
+            Text(t) => t.clone(),
+            Integer(i) => format!("{}", i),
+            Boolean(b) => format!("{}", if *b { 1 } else { 0 }),
+            Float(f) => format!("{}", f),
+            Color(c) => format!("{},{},{},{}", c.red, c.green, c.blue, c.alpha),
+            Choice(c) => c.variant().into(),
+            Stops(s) => s.iter()
+                .map(|s| {
+                    let c = s.color;
+                    format!("{}:{},{},{},{}", s.position, c.red, c.green, c.blue, c.alpha)
+                })
+                .collect::<Vec<_>>()
+                .join("; "),
+            Points(p) => p.iter()
+                .map(|p| format!("{},{}", p.x, p.y))
+                .collect::<Vec<_>>()
+                .join("; "),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Unknown,
+    WrongKind,
+    OutOfBounds,
+}
+
+/// Error for accessing a setting that the process doesn't have or giving it an invalid value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SettingError {
+    pub process: &'static str,
+    pub key: String,
+    pub reason: Reason,
+}
+
+impl SettingError {
+    pub fn unknown(process: &'static str, key: &str) -> SettingError {
+        SettingError::invalid(process, key, Reason::Unknown)
+    }
+
+    pub fn invalid(process: &'static str, key: &str, reason: Reason) -> SettingError {
+        SettingError {
+            process: process,
+            key: key.into(),
+            reason: reason,
+        }
+    }
+}
+
+impl Display for SettingError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self.reason {
+            Reason::Unknown => write!(
+                fmt,
+                "Process \"{}\" has no setting \"{}\"",
+                self.process, self.key
+            ),
+            Reason::WrongKind => write!(
+                fmt,
+                "Setting \"{}\" of process \"{}\" got value of wrong kind",
+                self.key, self.process
+            ),
+            Reason::OutOfBounds => write!(
+                fmt,
+                "Setting \"{}\" of process \"{}\" got value out of bounds",
+                self.key, self.process
+            ),
+        }
+    }
+}
+
+impl Error for SettingError {
+    fn description(&self) -> &str {
+        "Invalid setting"
+    }
+}
+
 pub trait Process: ProcessClone {
-    fn setting(&self, &str) -> Setting;
-    fn setting_mut(&mut self, &str) -> SettingMut;
-    fn settings(&self) -> Vec<&'static str>;
+    fn name(&self) -> &'static str;
+    fn setting(&self, key: &str) -> Result<Setting, SettingError>;
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError>;
+    fn settings(&self) -> Vec<Descriptor>;
     fn max_in(&self) -> u32;
     fn max_out(&self) -> u32;
-    fn shader(&self, context: &mut Context) -> String;
+    fn shader(&self, context: &mut Context) -> Result<String, ShaderError>;
+    fn evaluate(&self, sampler: &Sampler, output: u32) -> Vec4;
+
+    /// Whether the input is read at other coordinates than the ones being evaluated.
+    ///
+    /// Such inputs are rendered to a texture in an earlier pass instead of being inlined.
+    fn sampled(&self, _: u32) -> bool {
+        false
+    }
+
+    /// Whether the process needs the minimum and maximum of the input over the whole texture.
+    ///
+    /// Such inputs are rendered to a texture in an earlier pass, which is then reduced to
+    /// its range.
+    fn reduced(&self, _: u32) -> bool {
+        false
+    }
+
+    /// Number of passes the process is rendered in, each reading the result of the previous one
+    /// with `Context::previous_at` or `Sampler::previous_at`.
+    ///
+    /// Stages before the last only render the first output.
+    fn stages(&self) -> u32 {
+        1
+    }
+
+    fn descriptor(&self, key: &str) -> Option<Descriptor> {
+        self.settings().into_iter().find(|d| d.name == key)
+    }
+
+    /// Sets the setting after validating the value against its descriptor.
+    fn set(&mut self, key: &str, value: Value) -> Result<(), SettingError> {
+        let descriptor = self.descriptor(key)
+            .ok_or_else(|| SettingError::unknown(self.name(), key))?;
+        descriptor
+            .validate(&value)
+            .map_err(|r| SettingError::invalid(self.name(), key, r))?;
+        let name = self.name();
+        self.setting_mut(key)?
+            .set(value)
+            .map_err(|r| SettingError::invalid(name, key, r))
+    }
 }
 
 pub trait ProcessClone {
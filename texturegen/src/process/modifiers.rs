@@ -1,46 +1,186 @@
-use shader::Context;
-use process::{Process, Setting, SettingMut};
+use Col;
+use cpu::{clamp, fract, hsl_to_rgb, hsv_to_rgb, modulo, rgb_to_hsl, rgb_to_hsv, Sampler, Vec2,
+          Vec4};
+use shader::{Context, ShaderError};
+use process::{Descriptor, Process, Setting, SettingError, SettingMut, Stop, Value};
+use process::curve::{curve, curve_shader};
+use process::ramp::{ramp, ramp_shader, Interpolation};
 
-#[derive(Clone, Debug)]
-pub enum Type {
-    Sobel,
-    FreiChen,
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(Types), IterVariantNames(TypeNames))]
+    pub enum Type {
+        Sobel,
+        FreiChen,
+    }
+}
+
+impl_choice!(Type);
+
+const SQRT_2: f32 = ::std::f32::consts::SQRT_2;
+const PI: f32 = ::std::f32::consts::PI;
+
+/// Offsets of the sampled neighbourhood, rows from top to bottom.
+const OFFSETS: [(f32, f32); 9] = [
+    (-1., 1.),
+    (0., 1.),
+    (1., 1.),
+    (-1., 0.),
+    (0., 0.),
+    (1., 0.),
+    (-1., -1.),
+    (0., -1.),
+    (1., -1.),
+];
+
+const SOBEL: [[f32; 9]; 2] = [
+    [-1., 0., 1., -2., 0., 2., -1., 0., 1.],
+    [1., 2., 1., 0., 0., 0., -1., -2., -1.],
+];
+
+/// Frei-Chen basis, first four of which span the edge subspace.
+const FREI_CHEN: [[f32; 9]; 9] = [
+    [1., SQRT_2, 1., 0., 0., 0., -1., -SQRT_2, -1.],
+    [1., 0., -1., SQRT_2, 0., -SQRT_2, 1., 0., -1.],
+    [0., -1., SQRT_2, 1., 0., -1., -SQRT_2, 1., 0.],
+    [SQRT_2, -1., 0., -1., 0., 1., 0., 1., -SQRT_2],
+    [0., 1., 0., -1., 0., -1., 0., 1., 0.],
+    [-1., 0., 1., 0., 0., 0., 1., 0., -1.],
+    [1., -2., 1., -2., 4., -2., 1., -2., 1.],
+    [-2., 1., -2., 1., 4., 1., -2., 1., -2.],
+    [1., 1., 1., 1., 1., 1., 1., 1., 1.],
+];
+
+/// Normalization of the Frei-Chen basis.
+const FREI_CHEN_SCALE: [f32; 9] = [
+    0.5 / SQRT_2,
+    0.5 / SQRT_2,
+    0.5 / SQRT_2,
+    0.5 / SQRT_2,
+    0.5,
+    0.5,
+    1. / 6.,
+    1. / 6.,
+    1. / 3.,
+];
+
+impl Type {
+    /// Adds code calculating edge strength from intensities of the neighbourhood.
+    fn shader(&self, ctx: &mut Context, samples: &[String]) -> (String, String) {
+        use self::Type::*;
+        let mut result = String::new();
+        let mut projections = vec![];
+        for (kernel, scale) in self.kernels() {
+            let temp = ctx.temporary();
+            let sum = kernel
+                .iter()
+                .zip(samples)
+                .filter(|&(w, _)| *w != 0.)
+                .map(|(w, s)| format!("{:?} * {}", w * scale, s))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            result.push_str(&format!("float {} = {};\n", temp, sum));
+            projections.push(format!("{t} * {t}", t = temp));
+        }
+        let strength = match *self {
+            Sobel => format!("sqrt({}) / 4.", projections.join(" + ")),
+            FreiChen => format!(
+                "sqrt(({}) / max({}, 1e-6))",
+                projections[..4].join(" + "),
+                projections.join(" + ")
+            ),
+        };
+        (result, strength)
+    }
+
+    fn strength(&self, samples: &[f32; 9]) -> f32 {
+        use self::Type::*;
+        let projections = self.kernels()
+            .into_iter()
+            .map(|(kernel, scale)| {
+                let p = kernel
+                    .iter()
+                    .zip(samples)
+                    .fold(0., |sum, (w, s)| sum + w * scale * s);
+                p * p
+            })
+            .collect::<Vec<f32>>();
+        match *self {
+            Sobel => projections.iter().sum::<f32>().sqrt() / 4.,
+            FreiChen => {
+                let edge = projections[..4].iter().sum::<f32>();
+                let all = projections.iter().sum::<f32>();
+                (edge / all.max(1e-6)).sqrt()
+            }
+        }
+    }
+
+    fn kernels(&self) -> Vec<(&'static [f32; 9], f32)> {
+        use self::Type::*;
+        match *self {
+            Sobel => SOBEL.iter().map(|k| (k, 1.)).collect(),
+            FreiChen => FREI_CHEN.iter().zip(FREI_CHEN_SCALE.iter().cloned()).collect(),
+        }
+    }
 }
 
+/// Marks places where intensity of the input changes faster than the threshold.
 #[derive(Clone, Debug)]
 pub struct EdgeDetect {
     threshold: f32,
+    distance: f32,
     edtype: Type,
 }
 
 impl EdgeDetect {
-    pub fn new(threshold: f32, edtype: Type) -> Box<Process> {
+    pub fn new(threshold: f32, distance: f32, edtype: Type) -> Box<Process> {
         Box::new(EdgeDetect {
             threshold: threshold,
+            distance: distance,
             edtype: edtype,
         })
     }
 }
 
 impl Process for EdgeDetect {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "edge_detect"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
+        Ok(match key {
             "threshold" => Float(&self.threshold),
-            _ => panic!(),
-        }
+            "distance" => Float(&self.distance),
+            "type" => Choice(&self.edtype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
+        Ok(match key {
             "threshold" => Float(&mut self.threshold),
-            _ => panic!(),
-        }
+            "distance" => Float(&mut self.distance),
+            "type" => Choice(&mut self.edtype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
+    fn settings(&self) -> Vec<Descriptor> {
         vec![
-            "threshold",
-            // ("type".into(), Setting::EdgeDetectType(&mut self.edtype))
+            Descriptor::new("threshold", Value::Float(0.5), "Gradient needed for an edge")
+                .min(0.)
+                .max(1.)
+                .step(0.05),
+            Descriptor::new(
+                "distance",
+                Value::Float(0.002),
+                "Distance between sampled neighbours in texture coordinates",
+            ).min(0.)
+                .step(0.001),
+            Descriptor::new(
+                "type",
+                Value::Choice("Sobel".into()),
+                "Kernel used for finding the gradient",
+            ),
         ]
     }
     fn max_in(&self) -> u32 {
@@ -49,17 +189,55 @@ impl Process for EdgeDetect {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
-        let threshold = self.threshold;
-        // TODO: Edge detection using first order methods requires evaluation of parents in 9 different places.
-        format!(
-            "vec4 {} = vec4({}, {}, {}, {});\n",
-            ctx.output(0),
-            threshold,
-            threshold,
-            threshold,
-            threshold,
-        )
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        if ctx.input(0).is_none() {
+            return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?));
+        }
+        let mut result = String::new();
+        let mut samples = vec![];
+        for &(x, y) in &OFFSETS {
+            let temp = ctx.temporary();
+            let coords = format!(
+                "{} + vec2({:?}, {:?})",
+                ctx.coords(),
+                x * self.distance,
+                y * self.distance
+            );
+            result.push_str(&format!(
+                "float {} = dot({}.rgb, vec3(1. / 3.));\n",
+                temp,
+                ctx.input_at(0, &coords).unwrap()
+            ));
+            samples.push(temp.to_string());
+        }
+        let (code, strength) = self.edtype.shader(ctx, &samples);
+        result.push_str(&code);
+        result.push_str(&format!(
+            "vec4 {} = vec4(vec3(step({}, {})), 1.);\n",
+            ctx.output(0)?,
+            self.threshold,
+            strength
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        if sampler.input(0).is_none() {
+            return Vec4::splat(0.);
+        }
+        let mut samples = [0.; 9];
+        for (sample, &(x, y)) in samples.iter_mut().zip(&OFFSETS) {
+            let coords = sampler.coords() + Vec2::new(x, y) * self.distance;
+            let c = sampler.input_at(0, coords).unwrap_or_default();
+            *sample = (c.r + c.g + c.b) / 3.;
+        }
+        if self.edtype.strength(&samples) >= self.threshold {
+            Vec4::gray(1.)
+        } else {
+            Vec4::gray(0.)
+        }
+    }
+    fn sampled(&self, _: u32) -> bool {
+        true
     }
 }
 
@@ -77,22 +255,33 @@ impl Select {
 }
 
 impl Process for Select {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "select"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
+        Ok(match key {
             "threshold" => Float(&self.threshold),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
+        Ok(match key {
             "threshold" => Float(&mut self.threshold),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
-        vec!["threshold"]
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "threshold",
+                Value::Float(0.5),
+                "Brightness of the second input above which the third input is selected",
+            ).min(0.)
+                .max(1.)
+                .step(0.05),
+        ]
     }
     fn max_in(&self) -> u32 {
         3
@@ -100,19 +289,31 @@ impl Process for Select {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
         if let (Some(a), Some(t), Some(b)) = (ctx.input(0), ctx.input(1), ctx.input(2)) {
-            let mut res = format!("vec4 {} = {};\n", ctx.output(0), a);
+            let mut res = format!("vec4 {} = {};\n", ctx.output(0)?, a);
             res.push_str(&format!(
                 "if(({t}.r * 0.33 + {t}.g * 0.33 + {t}.b * 0.33) > {}) {{\n",
                 self.threshold,
                 t = t
             ));
-            res.push_str(&format!("  {} = {};\n", ctx.output(0), b));
+            res.push_str(&format!("  {} = {};\n", ctx.output(0)?, b));
             res.push_str(&format!("}}\n"));
-            res
+            Ok(res)
         } else {
-            format!("vec4 {} = vec4(0);\n", ctx.output(0))
+            Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?))
+        }
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let inputs = (sampler.input(0), sampler.input(1), sampler.input(2));
+        if let (Some(a), Some(t), Some(b)) = inputs {
+            if (t.r * 0.33 + t.g * 0.33 + t.b * 0.33) > self.threshold {
+                b
+            } else {
+                a
+            }
+        } else {
+            Vec4::splat(0.)
         }
     }
 }
@@ -129,22 +330,31 @@ impl Invert {
 }
 
 impl Process for Invert {
-    fn setting(&self, key: &str) -> Setting {
+    fn name(&self) -> &'static str {
+        "invert"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
         use process::Setting::*;
-        match key {
+        Ok(match key {
             "alpha" => Boolean(&self.alpha),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn setting_mut(&mut self, key: &str) -> SettingMut {
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
         use process::SettingMut::*;
-        match key {
+        Ok(match key {
             "alpha" => Boolean(&mut self.alpha),
-            _ => panic!(),
-        }
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
     }
-    fn settings(&self) -> Vec<&'static str> {
-        vec!["alpha"]
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "alpha",
+                Value::Boolean(false),
+                "Inverts alpha instead of colors",
+            ),
+        ]
     }
     fn max_in(&self) -> u32 {
         1
@@ -152,23 +362,1665 @@ impl Process for Invert {
     fn max_out(&self) -> u32 {
         1
     }
-    fn shader(&self, ctx: &mut Context) -> String {
-        if let Some(input) = ctx.input(0) {
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        Ok(if let Some(input) = ctx.input(0) {
             if self.alpha {
                 format!(
                     "vec4 {} = vec4({i}.rgb, 1 - {i}.a);\n",
-                    ctx.output(0),
+                    ctx.output(0)?,
                     i = input
                 )
             } else {
                 format!(
                     "vec4 {} = vec4(1 - {i}.r, 1 - {i}.g, 1 - {i}.b, {i}.a);\n",
-                    ctx.output(0),
+                    ctx.output(0)?,
                     i = input
                 )
             }
         } else {
-            format!("vec4 {} = vec4(0);\n", ctx.output(0))
+            format!("vec4 {} = vec4(0);\n", ctx.output(0)?)
+        })
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        if let Some(i) = sampler.input(0) {
+            if self.alpha {
+                Vec4::new(i.r, i.g, i.b, 1. - i.a)
+            } else {
+                Vec4::new(1. - i.r, 1. - i.g, 1. - i.b, i.a)
+            }
+        } else {
+            Vec4::splat(0.)
+        }
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(Channels), IterVariantNames(ChannelNames))]
+    pub enum Channel {
+        Luminance,
+        Red,
+        Green,
+        Blue,
+        Alpha,
+    }
+}
+
+impl_choice!(Channel);
+
+/// Weights of linear red, green and blue in relative luminance.
+const LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+impl Channel {
+    /// GLSL expression for the channel of `input`, which is a `vec4`.
+    pub fn shader(&self, input: &str) -> String {
+        use self::Channel::*;
+        match *self {
+            Luminance => format!(
+                "dot({}.rgb, vec3({:?}, {:?}, {:?}))",
+                input, LUMINANCE[0], LUMINANCE[1], LUMINANCE[2]
+            ),
+            Red => format!("{}.r", input),
+            Green => format!("{}.g", input),
+            Blue => format!("{}.b", input),
+            Alpha => format!("{}.a", input),
+        }
+    }
+
+    pub fn value(&self, c: Vec4) -> f32 {
+        use self::Channel::*;
+        match *self {
+            Luminance => c.r * LUMINANCE[0] + c.g * LUMINANCE[1] + c.b * LUMINANCE[2],
+            Red => c.r,
+            Green => c.g,
+            Blue => c.b,
+            Alpha => c.a,
+        }
+    }
+}
+
+/// Colors the input by looking up a channel of it from a color ramp.
+#[derive(Clone, Debug)]
+pub struct GradientMap {
+    channel: Channel,
+    interpolation: Interpolation,
+    stops: Vec<Stop>,
+}
+
+impl GradientMap {
+    pub fn new(channel: Channel, interpolation: Interpolation, stops: Vec<Stop>) -> Box<Process> {
+        Box::new(GradientMap {
+            channel: channel,
+            interpolation: interpolation,
+            stops: stops,
+        })
+    }
+}
+
+impl Process for GradientMap {
+    fn name(&self) -> &'static str {
+        "gradient_map"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "channel" => Choice(&self.channel),
+            "interpolation" => Choice(&self.interpolation),
+            "stops" => Stops(&self.stops),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "channel" => Choice(&mut self.channel),
+            "interpolation" => Choice(&mut self.interpolation),
+            "stops" => Stops(&mut self.stops),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "channel",
+                Value::Choice("Luminance".into()),
+                "Part of the input used as position on the ramp",
+            ),
+            Descriptor::new(
+                "interpolation",
+                Value::Choice("Linear".into()),
+                "How colors change between stops",
+            ),
+            Descriptor::new(
+                "stops",
+                Value::Stops(vec![
+                    Stop::new(0., Col::new(0., 0., 0., 1.)),
+                    Stop::new(1., Col::new(1., 1., 1., 1.)),
+                ]),
+                "Colors and their positions from 0 to 1",
+            ).min(0.)
+                .max(1.),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        if let Some(input) = ctx.input(0) {
+            let t = ctx.temporary();
+            Ok(format!(
+                "float {} = {};\nvec4 {} = {};\n",
+                t,
+                self.channel.shader(&input.to_string()),
+                ctx.output(0)?,
+                ramp_shader(&self.stops, &t.to_string(), self.interpolation)
+            ))
+        } else {
+            Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?))
+        }
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        if let Some(i) = sampler.input(0) {
+            ramp(&self.stops, self.channel.value(i), self.interpolation)
+        } else {
+            Vec4::splat(0.)
+        }
+    }
+}
+
+/// Moves, scales, rotates and repeats the input by remapping the coordinates it's evaluated at.
+#[derive(Clone, Debug)]
+pub struct Transform {
+    x: f32,
+    y: f32,
+    scale_x: f32,
+    scale_y: f32,
+    rotation: f32,
+    pivot_x: f32,
+    pivot_y: f32,
+    repeat_x: u32,
+    repeat_y: u32,
+    mirror: bool,
+}
+
+impl Transform {
+    pub fn new() -> Box<Process> {
+        Box::new(Transform {
+            x: 0.,
+            y: 0.,
+            scale_x: 1.,
+            scale_y: 1.,
+            rotation: 0.,
+            pivot_x: 0.5,
+            pivot_y: 0.5,
+            repeat_x: 1,
+            repeat_y: 1,
+            mirror: false,
+        })
+    }
+
+    fn repeat(&self) -> Vec2 {
+        Vec2::new(self.repeat_x.max(1) as f32, self.repeat_y.max(1) as f32)
+    }
+
+    fn scale(&self) -> Vec2 {
+        Vec2::new(self.scale_x, self.scale_y).map(|s| if s.abs() < 1e-6 { 1e-6 } else { s })
+    }
+
+    /// Coordinates of the input that end up at `coords`.
+    fn coords(&self, coords: Vec2) -> Vec2 {
+        let c = coords * self.repeat();
+        let c = if self.mirror {
+            c.map(|c| 1. - (modulo(c, 2.) - 1.).abs())
+        } else {
+            c.map(fract)
+        };
+        let pivot = Vec2::new(self.pivot_x, self.pivot_y);
+        let q = c - pivot - Vec2::new(self.x, self.y);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let q = Vec2::new(q.x * cos + q.y * sin, q.y * cos - q.x * sin);
+        q / self.scale() + pivot
+    }
+}
+
+impl Process for Transform {
+    fn name(&self) -> &'static str {
+        "transform"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "offset x" => Float(&self.x),
+            "offset y" => Float(&self.y),
+            "scale x" => Float(&self.scale_x),
+            "scale y" => Float(&self.scale_y),
+            "rotation" => Float(&self.rotation),
+            "pivot x" => Float(&self.pivot_x),
+            "pivot y" => Float(&self.pivot_y),
+            "repeat x" => Integer(&self.repeat_x),
+            "repeat y" => Integer(&self.repeat_y),
+            "mirror" => Boolean(&self.mirror),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "offset x" => Float(&mut self.x),
+            "offset y" => Float(&mut self.y),
+            "scale x" => Float(&mut self.scale_x),
+            "scale y" => Float(&mut self.scale_y),
+            "rotation" => Float(&mut self.rotation),
+            "pivot x" => Float(&mut self.pivot_x),
+            "pivot y" => Float(&mut self.pivot_y),
+            "repeat x" => Integer(&mut self.repeat_x),
+            "repeat y" => Integer(&mut self.repeat_y),
+            "mirror" => Boolean(&mut self.mirror),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new("offset x", Value::Float(0.), "Horizontal movement").step(0.05),
+            Descriptor::new("offset y", Value::Float(0.), "Vertical movement").step(0.05),
+            Descriptor::new("scale x", Value::Float(1.), "Horizontal scale").step(0.1),
+            Descriptor::new("scale y", Value::Float(1.), "Vertical scale").step(0.1),
+            Descriptor::new("rotation", Value::Float(0.), "Rotation in degrees").step(15.),
+            Descriptor::new(
+                "pivot x",
+                Value::Float(0.5),
+                "Horizontal position of the point scaled and rotated around",
+            ).step(0.05),
+            Descriptor::new(
+                "pivot y",
+                Value::Float(0.5),
+                "Vertical position of the point scaled and rotated around",
+            ).step(0.05),
+            Descriptor::new("repeat x", Value::Integer(1), "Horizontal number of copies")
+                .min(1.)
+                .step(1.),
+            Descriptor::new("repeat y", Value::Integer(1), "Vertical number of copies")
+                .min(1.)
+                .step(1.),
+            Descriptor::new(
+                "mirror",
+                Value::Boolean(false),
+                "Flips every other copy so that they meet seamlessly",
+            ),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        if ctx.input(0).is_none() {
+            return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?));
+        }
+        let repeat = self.repeat();
+        let scale = self.scale();
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let c = ctx.temporary();
+        let mut result = format!(
+            "vec2 {c} = {} * vec2({:?}, {:?});\n",
+            ctx.coords(),
+            repeat.x,
+            repeat.y,
+            c = c
+        );
+        if self.mirror {
+            result.push_str(&format!("{c} = 1. - abs(mod({c}, 2.) - 1.);\n", c = c));
+        } else {
+            result.push_str(&format!("{c} = fract({c});\n", c = c));
+        }
+        result.push_str(&format!(
+            "{c} -= vec2({:?}, {:?});\n\
+             {c} = vec2({c}.x * {cos:?} + {c}.y * {sin:?}, {c}.y * {cos:?} - {c}.x * {sin:?});\n\
+             {c} = {c} / vec2({:?}, {:?}) + vec2({:?}, {:?});\n",
+            self.pivot_x + self.x,
+            self.pivot_y + self.y,
+            scale.x,
+            scale.y,
+            self.pivot_x,
+            self.pivot_y,
+            c = c,
+            cos = cos,
+            sin = sin
+        ));
+        result.push_str(&format!(
+            "vec4 {} = {};\n",
+            ctx.output(0)?,
+            ctx.input_at(0, &c.to_string()).unwrap()
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        sampler
+            .input_mapped(0, &|c| self.coords(c))
+            .unwrap_or(Vec4::splat(0.))
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(WarpTypes), IterVariantNames(WarpTypeNames))]
+    pub enum WarpType {
+        Directional,
+        Vector,
+    }
+}
+
+impl_choice!(WarpType);
+
+/// Samples the first input at coordinates moved by the second one.
+///
+/// Displacement is centered on 0.5, so mid gray leaves the first input in place.
+/// Directional warp moves along the angle by luminance, while vector warp moves
+/// horizontally by red and vertically by green.
+#[derive(Clone, Debug)]
+pub struct Warp {
+    intensity: f32,
+    angle: f32,
+    wtype: WarpType,
+}
+
+impl Warp {
+    pub fn new(intensity: f32, angle: f32, wtype: WarpType) -> Box<Process> {
+        Box::new(Warp {
+            intensity: intensity,
+            angle: angle,
+            wtype: wtype,
+        })
+    }
+
+    /// Movement of directional warp by white, as displacement goes from -0.5 to 0.5.
+    fn direction(&self) -> Vec2 {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        Vec2::new(cos, sin) * (self.intensity * 2.)
+    }
+}
+
+impl Process for Warp {
+    fn name(&self) -> &'static str {
+        "warp"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "intensity" => Float(&self.intensity),
+            "angle" => Float(&self.angle),
+            "type" => Choice(&self.wtype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "intensity" => Float(&mut self.intensity),
+            "angle" => Float(&mut self.angle),
+            "type" => Choice(&mut self.wtype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "intensity",
+                Value::Float(0.1),
+                "Largest movement in texture coordinates",
+            ).step(0.01),
+            Descriptor::new(
+                "angle",
+                Value::Float(0.),
+                "Direction of directional warp in degrees",
+            ).step(15.),
+            Descriptor::new(
+                "type",
+                Value::Choice("Directional".into()),
+                "How the second input moves the first",
+            ),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        2
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        use self::WarpType::*;
+        let displacement = match (ctx.input(0), ctx.input(1)) {
+            (None, _) => return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?)),
+            (Some(i), None) => return Ok(format!("vec4 {} = {};\n", ctx.output(0)?, i)),
+            (Some(_), Some(d)) => d,
+        };
+        let offset = match self.wtype {
+            Directional => {
+                let dir = self.direction();
+                format!(
+                    "(dot({}.rgb, vec3({:?}, {:?}, {:?})) - 0.5) * vec2({:?}, {:?})",
+                    displacement, LUMINANCE[0], LUMINANCE[1], LUMINANCE[2], dir.x, dir.y
+                )
+            }
+            Vector => format!("({}.rg - 0.5) * {:?}", displacement, self.intensity * 2.),
+        };
+        let c = ctx.temporary();
+        Ok(format!(
+            "vec2 {} = {} + {};\nvec4 {} = {};\n",
+            c,
+            ctx.coords(),
+            offset,
+            ctx.output(0)?,
+            ctx.input_at(0, &c.to_string()).unwrap()
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        use self::WarpType::*;
+        match (sampler.input(0), sampler.input(1)) {
+            (None, _) => return Vec4::splat(0.),
+            (Some(i), None) => return i,
+            (Some(_), Some(_)) => {}
+        }
+        // Displacement is evaluated at the neighbouring coordinates as well to get derivatives.
+        let warp = |c: Vec2| {
+            let d = sampler.input_at(1, c).unwrap();
+            c + match self.wtype {
+                Directional => self.direction() * (Channel::Luminance.value(d) - 0.5),
+                Vector => (Vec2::new(d.r, d.g) - Vec2::splat(0.5)) * (self.intensity * 2.),
+            }
+        };
+        sampler.input_mapped(0, &warp).unwrap_or(Vec4::splat(0.))
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(BlurTypes), IterVariantNames(BlurTypeNames))]
+    pub enum BlurType {
+        Box,
+        Gaussian,
+        Directional,
+    }
+}
+
+impl_choice!(BlurType);
+
+/// Averages the input over a neighbourhood of given radius in texture coordinates.
+///
+/// Box and gaussian blur are separable, so they are rendered in two stages
+/// blurring horizontally and then vertically. Samples are about a pixel apart
+/// up to `MAX_TAPS` on each side, so the result looks the same at any size
+/// while small radii stay cheap. Gaussian weights fall to about 1% at the radius.
+#[derive(Clone, Debug)]
+pub struct Blur {
+    radius: f32,
+    angle: f32,
+    btype: BlurType,
+}
+
+/// Largest number of samples on each side of the center.
+const MAX_TAPS: f32 = 64.;
+
+impl Blur {
+    pub fn new(radius: f32, angle: f32, btype: BlurType) -> Box<Process> {
+        Box::new(Blur {
+            radius: radius,
+            angle: angle,
+            btype: btype,
+        })
+    }
+
+    /// Unit vector the samples of the stage are taken along.
+    fn direction(&self, stage: u32) -> Vec2 {
+        match self.btype {
+            BlurType::Directional => {
+                let (sin, cos) = self.angle.to_radians().sin_cos();
+                Vec2::new(cos, sin)
+            }
+            _ if stage == 0 => Vec2::new(1., 0.),
+            _ => Vec2::new(0., 1.),
+        }
+    }
+}
+
+impl Process for Blur {
+    fn name(&self) -> &'static str {
+        "blur"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "radius" => Float(&self.radius),
+            "angle" => Float(&self.angle),
+            "type" => Choice(&self.btype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "radius" => Float(&mut self.radius),
+            "angle" => Float(&mut self.angle),
+            "type" => Choice(&mut self.btype),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "radius",
+                Value::Float(0.01),
+                "Distance to the furthest sample in texture coordinates",
+            ).min(0.)
+                .step(0.005),
+            Descriptor::new(
+                "angle",
+                Value::Float(0.),
+                "Direction of directional blur in degrees",
+            ).step(15.),
+            Descriptor::new(
+                "type",
+                Value::Choice("Gaussian".into()),
+                "Shape and weights of the neighbourhood",
+            ),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        if ctx.input(0).is_none() {
+            return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?));
+        }
+        let direction = self.direction(ctx.stage());
+        let n = ctx.temporary();
+        let step = ctx.temporary();
+        let falloff = ctx.temporary();
+        let sum = ctx.temporary();
+        let total = ctx.temporary();
+        let weight = ctx.temporary();
+        // Same number of samples as `evaluate`, from the length of a pixel along the direction.
+        let mut result = format!(
+            "vec2 {d} = vec2({:?}, {:?});\n\
+             float {n} = clamp(ceil({:?} / max(dot(abs({d}), fwidth({})), 1e-6)), 1., {:?});\n\
+             {d} *= {:?} / {n};\n\
+             float {f} = 4.5 / ({n} * {n});\n\
+             vec4 {s} = vec4(0);\nfloat {t} = 0.;\n\
+             for(int i = -int({n}); i <= int({n}); i++) {{\n",
+            direction.x,
+            direction.y,
+            self.radius,
+            ctx.coords(),
+            MAX_TAPS,
+            self.radius,
+            d = step,
+            n = n,
+            f = falloff,
+            s = sum,
+            t = total
+        );
+        let w = match self.btype {
+            BlurType::Gaussian => format!("exp(-float(i * i) * {})", falloff),
+            _ => "1.".into(),
+        };
+        let coords = format!("{} + {} * float(i)", ctx.coords(), step);
+        let value = if ctx.stage() == 0 {
+            ctx.input_at(0, &coords)
+        } else {
+            ctx.previous_at(&coords)
+        };
+        result.push_str(&format!(
+            "float {w} = {};\n{s} += {w} * {};\n{t} += {w};\n}}\n",
+            w,
+            value.unwrap(),
+            w = weight,
+            s = sum,
+            t = total
+        ));
+        result.push_str(&format!("vec4 {} = {} / {};\n", ctx.output(0)?, sum, total));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        if sampler.input(0).is_none() {
+            return Vec4::splat(0.);
+        }
+        let direction = self.direction(sampler.stage());
+        let width = sampler.fwidth();
+        let pixel = direction.x.abs() * width.x + direction.y.abs() * width.y;
+        let n = clamp((self.radius / pixel.max(1e-6)).ceil(), 1., MAX_TAPS);
+        let step = direction * (self.radius / n);
+        let falloff = 4.5 / (n * n);
+        let mut sum = Vec4::splat(0.);
+        let mut total = 0.;
+        for i in -(n as i32)..n as i32 + 1 {
+            let w = match self.btype {
+                BlurType::Gaussian => (-((i * i) as f32) * falloff).exp(),
+                _ => 1.,
+            };
+            let coords = sampler.coords() + step * i as f32;
+            let c = if sampler.stage() == 0 {
+                sampler.input_at(0, coords)
+            } else {
+                sampler.previous_at(coords)
+            };
+            sum = sum + c.unwrap() * w;
+            total += w;
+        }
+        sum / total
+    }
+    fn sampled(&self, _: u32) -> bool {
+        true
+    }
+    fn stages(&self) -> u32 {
+        match self.btype {
+            BlurType::Directional => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// Adds code reading luminance of the input at offsets from the evaluated coordinates.
+///
+/// Returns names of the variables holding the heights.
+fn heights_shader(ctx: &mut Context, offsets: &[Vec2], code: &mut String) -> Vec<String> {
+    let mut heights = vec![];
+    for offset in offsets {
+        let temp = ctx.temporary();
+        let coords = format!("{} + vec2({:?}, {:?})", ctx.coords(), offset.x, offset.y);
+        let input = ctx.input_at(0, &coords).expect("Input should exist when sampling heights");
+        code.push_str(&format!(
+            "float {} = {};\n",
+            temp,
+            Channel::Luminance.shader(&input)
+        ));
+        heights.push(temp.to_string());
+    }
+    heights
+}
+
+fn height(sampler: &Sampler, offset: Vec2) -> f32 {
+    let c = sampler
+        .input_at(0, sampler.coords() + offset)
+        .unwrap_or_default();
+    Channel::Luminance.value(c)
+}
+
+/// Central difference kernels for the neighbourhood in `OFFSETS`.
+const CENTRAL: [[f32; 9]; 2] = [
+    [0., 0., 0., -1., 0., 1., 0., 0., 0.],
+    [0., 1., 0., 0., 0., 0., 0., -1., 0.],
+];
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(Derivatives), IterVariantNames(DerivativeNames))]
+    pub enum Derivative {
+        Sobel,
+        CentralDifference,
+    }
+}
+
+impl_choice!(Derivative);
+
+impl Derivative {
+    /// Kernels for horizontal and vertical change, and the distance they span in samples.
+    fn kernels(&self) -> (&'static [[f32; 9]; 2], f32) {
+        match *self {
+            Derivative::Sobel => (&SOBEL, 8.),
+            Derivative::CentralDifference => (&CENTRAL, 2.),
+        }
+    }
+}
+
+/// Treats the input as height and outputs tangent space normals encoded as colors.
+///
+/// Like other colors the output is linear, so that the exported sRGB texture
+/// holds normals encoded the usual way.
+#[derive(Clone, Debug)]
+pub struct NormalMap {
+    strength: f32,
+    distance: f32,
+    directx: bool,
+    derivative: Derivative,
+}
+
+impl NormalMap {
+    pub fn new(
+        strength: f32,
+        distance: f32,
+        directx: bool,
+        derivative: Derivative,
+    ) -> Box<Process> {
+        Box::new(NormalMap {
+            strength: strength,
+            distance: distance,
+            directx: directx,
+            derivative: derivative,
+        })
+    }
+
+    /// Scale of weighted height differences that gives the slope times strength.
+    fn scale(&self) -> f32 {
+        let (_, span) = self.derivative.kernels();
+        self.strength / (span * self.distance.max(1e-6))
+    }
+
+    /// Offsets of the samples which any kernel uses.
+    fn offsets(&self) -> Vec<(usize, Vec2)> {
+        let (kernels, _) = self.derivative.kernels();
+        OFFSETS
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| kernels.iter().any(|k| k[i] != 0.))
+            .map(|(i, &(x, y))| (i, Vec2::new(x, y) * self.distance))
+            .collect()
+    }
+}
+
+impl Process for NormalMap {
+    fn name(&self) -> &'static str {
+        "normal_map"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "strength" => Float(&self.strength),
+            "distance" => Float(&self.distance),
+            "directx" => Boolean(&self.directx),
+            "derivative" => Choice(&self.derivative),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "strength" => Float(&mut self.strength),
+            "distance" => Float(&mut self.distance),
+            "directx" => Boolean(&mut self.directx),
+            "derivative" => Choice(&mut self.derivative),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "strength",
+                Value::Float(0.05),
+                "Height of white in texture widths",
+            ).min(0.)
+                .step(0.01),
+            Descriptor::new(
+                "distance",
+                Value::Float(0.002),
+                "Distance between sampled neighbours in texture coordinates",
+            ).min(0.)
+                .step(0.001),
+            Descriptor::new(
+                "directx",
+                Value::Boolean(false),
+                "Flips the green channel for the DirectX convention",
+            ),
+            Descriptor::new(
+                "derivative",
+                Value::Choice("Sobel".into()),
+                "How the slope is estimated from the neighbours",
+            ),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        if ctx.input(0).is_none() {
+            return Ok(format!("vec4 {} = vec4(0.5, 0.5, 1., 1.);\n", ctx.output(0)?));
+        }
+        let (kernels, _) = self.derivative.kernels();
+        let offsets = self.offsets();
+        let mut result = String::new();
+        let positions = offsets.iter().map(|&(_, o)| o).collect::<Vec<_>>();
+        let heights = heights_shader(ctx, &positions, &mut result);
+        let slopes = kernels
+            .iter()
+            .map(|kernel| {
+                offsets
+                    .iter()
+                    .zip(&heights)
+                    .filter(|&(&(i, _), _)| kernel[i] != 0.)
+                    .map(|(&(i, _), h)| format!("{:?} * {}", kernel[i] * self.scale(), h))
+                    .collect::<Vec<_>>()
+                    .join(" + ")
+            })
+            .collect::<Vec<_>>();
+        let normal = ctx.temporary();
+        result.push_str(&format!(
+            "vec3 {} = normalize(vec3(-({}), {}({}), 1.));\n",
+            normal,
+            slopes[0],
+            if self.directx { "" } else { "-" },
+            slopes[1]
+        ));
+        result.push_str(&format!(
+            "vec4 {} = vec4({} * 0.5 + 0.5, 1.);\n",
+            ctx.output(0)?,
+            normal
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        if sampler.input(0).is_none() {
+            return Col::new(0.5, 0.5, 1., 1.).into();
+        }
+        let (kernels, _) = self.derivative.kernels();
+        let mut slopes = [0.; 2];
+        for (i, offset) in self.offsets() {
+            let h = height(sampler, offset);
+            for (slope, kernel) in slopes.iter_mut().zip(kernels) {
+                *slope += kernel[i] * self.scale() * h;
+            }
+        }
+        let y = if self.directx { slopes[1] } else { -slopes[1] };
+        let length = (slopes[0] * slopes[0] + y * y + 1.).sqrt();
+        Col::new(
+            -slopes[0] / length * 0.5 + 0.5,
+            y / length * 0.5 + 0.5,
+            1. / length * 0.5 + 0.5,
+            1.,
+        ).into()
+    }
+    fn sampled(&self, _: u32) -> bool {
+        true
+    }
+}
+
+/// Number of samples along each direction when searching for the horizon.
+const AO_STEPS: u32 = 4;
+
+/// Darkens places of the input as height that are surrounded by higher ones.
+///
+/// Looks for the highest horizon on both sides along each direction, treating
+/// white as being as high as the radius is long. Horizons of opposite sides
+/// are summed so that slopes without anything around them aren't darkened.
+#[derive(Clone, Debug)]
+pub struct AmbientOcclusion {
+    radius: f32,
+    samples: u32,
+    intensity: f32,
+}
+
+impl AmbientOcclusion {
+    pub fn new(radius: f32, samples: u32, intensity: f32) -> Box<Process> {
+        Box::new(AmbientOcclusion {
+            radius: radius,
+            samples: samples,
+            intensity: intensity,
+        })
+    }
+
+    fn samples(&self) -> u32 {
+        self.samples.max(1)
+    }
+}
+
+impl Process for AmbientOcclusion {
+    fn name(&self) -> &'static str {
+        "ambient_occlusion"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "radius" => Float(&self.radius),
+            "samples" => Integer(&self.samples),
+            "intensity" => Float(&self.intensity),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "radius" => Float(&mut self.radius),
+            "samples" => Integer(&mut self.samples),
+            "intensity" => Float(&mut self.intensity),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "radius",
+                Value::Float(0.05),
+                "Distance searched for occluders in texture coordinates",
+            ).min(0.)
+                .step(0.01),
+            Descriptor::new("samples", Value::Integer(8), "Number of directions searched")
+                .min(1.)
+                .max(32.)
+                .step(1.),
+            Descriptor::new("intensity", Value::Float(1.), "How dark occluded places get")
+                .min(0.)
+                .step(0.1),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let input = match ctx.input(0) {
+            Some(i) => i,
+            None => return Ok(format!("vec4 {} = vec4(1);\n", ctx.output(0)?)),
+        };
+        let center = ctx.temporary();
+        let occlusion = ctx.temporary();
+        let dir = ctx.temporary();
+        let horizons = ctx.temporary();
+        let slopes = ctx.temporary();
+        let ahead = format!("{} + {} * float(i)", ctx.coords(), dir);
+        let behind = format!("{} - {} * float(i)", ctx.coords(), dir);
+        let ahead = Channel::Luminance.shader(&ctx.input_at(0, &ahead).unwrap());
+        let behind = Channel::Luminance.shader(&ctx.input_at(0, &behind).unwrap());
+        Ok(format!(
+            "float {c} = {};\n\
+             float {o} = 0.;\n\
+             for(int k = 0; k < {n}; k++) {{\n\
+             float a = float(k) * {:?};\n\
+             vec2 {d} = vec2(cos(a), sin(a)) * {:?};\n\
+             vec2 {h} = vec2(-1.);\n\
+             for(int i = 1; i <= {steps}; i++) {{\n\
+             vec2 {s} = (vec2({}, {}) - {c}) * {steps:?} / float(i);\n\
+             {h} = max({h}, {s} / sqrt(1. + {s} * {s}));\n\
+             }}\n\
+             {o} += max({h}.x + {h}.y, 0.);\n\
+             }}\n\
+             vec4 {} = vec4(vec3(clamp(1. - {:?} * {o}, 0., 1.)), 1.);\n",
+            Channel::Luminance.shader(&input.to_string()),
+            PI / self.samples() as f32,
+            self.radius / AO_STEPS as f32,
+            ahead,
+            behind,
+            ctx.output(0)?,
+            self.intensity * 0.5 / self.samples() as f32,
+            c = center,
+            o = occlusion,
+            d = dir,
+            h = horizons,
+            s = slopes,
+            n = self.samples(),
+            steps = AO_STEPS as f32
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        if sampler.input(0).is_none() {
+            return Vec4::splat(1.);
+        }
+        let center = height(sampler, Vec2::splat(0.));
+        // Sine of the elevation of the sample at `i` steps along `dir`
+        let horizon = |dir: Vec2, i: f32| {
+            let s = (height(sampler, dir * i) - center) * AO_STEPS as f32 / i;
+            s / (1. + s * s).sqrt()
+        };
+        let mut occlusion = 0.;
+        for k in 0..self.samples() {
+            let a = k as f32 * PI / self.samples() as f32;
+            let dir = Vec2::new(a.cos(), a.sin()) * (self.radius / AO_STEPS as f32);
+            let (mut ahead, mut behind): (f32, f32) = (-1., -1.);
+            for i in 1..AO_STEPS + 1 {
+                let i = i as f32;
+                ahead = ahead.max(horizon(dir, i));
+                behind = behind.max(horizon(-dir, i));
+            }
+            occlusion += (ahead + behind).max(0.);
+        }
+        let occlusion = self.intensity * 0.5 / self.samples() as f32 * occlusion;
+        Vec4::gray(clamp(1. - occlusion, 0., 1.))
+    }
+    fn sampled(&self, _: u32) -> bool {
+        true
+    }
+}
+
+/// Compares the input as height to its surroundings, so that convex places are
+/// brighter and concave ones darker than the mid gray of flat ones.
+#[derive(Clone, Debug)]
+pub struct Curvature {
+    distance: f32,
+    intensity: f32,
+}
+
+impl Curvature {
+    pub fn new(distance: f32, intensity: f32) -> Box<Process> {
+        Box::new(Curvature {
+            distance: distance,
+            intensity: intensity,
+        })
+    }
+
+    fn offsets(&self) -> Vec<Vec2> {
+        OFFSETS
+            .iter()
+            .filter(|&&(x, y)| x != 0. || y != 0.)
+            .map(|&(x, y)| Vec2::new(x, y) * self.distance)
+            .collect()
+    }
+}
+
+impl Process for Curvature {
+    fn name(&self) -> &'static str {
+        "curvature"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "distance" => Float(&self.distance),
+            "intensity" => Float(&self.intensity),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "distance" => Float(&mut self.distance),
+            "intensity" => Float(&mut self.intensity),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "distance",
+                Value::Float(0.005),
+                "Distance between sampled neighbours in texture coordinates",
+            ).min(0.)
+                .step(0.001),
+            Descriptor::new(
+                "intensity",
+                Value::Float(1.),
+                "How much the curvature brightens or darkens",
+            ).min(0.)
+                .step(0.1),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let input = match ctx.input(0) {
+            Some(i) => i,
+            None => return Ok(format!("vec4 {} = vec4(vec3(0.5), 1.);\n", ctx.output(0)?)),
+        };
+        let mut result = String::new();
+        let heights = heights_shader(ctx, &self.offsets(), &mut result);
+        result.push_str(&format!(
+            "vec4 {} = vec4(vec3(clamp(0.5 + {:?} * ({} - ({}) / 8.), 0., 1.)), 1.);\n",
+            ctx.output(0)?,
+            self.intensity,
+            Channel::Luminance.shader(&input.to_string()),
+            heights.join(" + ")
+        ));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        if sampler.input(0).is_none() {
+            return Vec4::new(0.5, 0.5, 0.5, 1.);
+        }
+        let center = height(sampler, Vec2::splat(0.));
+        let mean = self.offsets()
+            .into_iter()
+            .map(|o| height(sampler, o))
+            .sum::<f32>() / 8.;
+        Vec4::gray(clamp(0.5 + self.intensity * (center - mean), 0., 1.))
+    }
+    fn sampled(&self, _: u32) -> bool {
+        true
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(ChannelModes), IterVariantNames(ChannelModeNames))]
+    pub enum ChannelMode {
+        Rgb,
+        Luminance,
+        Red,
+        Green,
+        Blue,
+        Alpha,
+    }
+}
+
+impl_choice!(ChannelMode);
+
+impl ChannelMode {
+    /// Adds code remapping the chosen channels of the input with `fun` and returns the result.
+    ///
+    /// Luminance is remapped by moving all color channels by the same amount,
+    /// which keeps the differences between them.
+    fn shader<F>(&self, ctx: &mut Context, input: &str, code: &mut String, mut fun: F) -> String
+    where
+        F: FnMut(&str, Channel) -> String,
+    {
+        use self::ChannelMode::*;
+        if *self == Luminance {
+            let l = ctx.temporary();
+            code.push_str(&format!(
+                "float {} = {};\n",
+                l,
+                Channel::Luminance.shader(input)
+            ));
+            let l = l.to_string();
+            return format!(
+                "{} + vec4(vec3({} - {}), 0.)",
+                input,
+                fun(&l, Channel::Luminance),
+                l
+            );
+        }
+        let mut channel = |c: Channel| fun(&c.shader(input), c);
+        match *self {
+            Rgb | Luminance => format!(
+                "vec4({}, {}, {}, {}.a)",
+                channel(Channel::Red),
+                channel(Channel::Green),
+                channel(Channel::Blue),
+                input
+            ),
+            Red => format!("vec4({}, {}.gba)", channel(Channel::Red), input),
+            Green => format!(
+                "vec4({i}.r, {}, {i}.ba)",
+                channel(Channel::Green),
+                i = input
+            ),
+            Blue => format!("vec4({i}.rg, {}, {i}.a)", channel(Channel::Blue), i = input),
+            Alpha => format!("vec4({}.rgb, {})", input, channel(Channel::Alpha)),
+        }
+    }
+
+    fn value<F: Fn(f32, Channel) -> f32>(&self, c: Vec4, fun: F) -> Vec4 {
+        use self::ChannelMode::*;
+        match *self {
+            Rgb => Vec4::new(
+                fun(c.r, Channel::Red),
+                fun(c.g, Channel::Green),
+                fun(c.b, Channel::Blue),
+                c.a,
+            ),
+            Luminance => {
+                let l = Channel::Luminance.value(c);
+                let d = fun(l, Channel::Luminance) - l;
+                Vec4::new(c.r + d, c.g + d, c.b + d, c.a)
+            }
+            Red => Vec4::new(fun(c.r, Channel::Red), c.g, c.b, c.a),
+            Green => Vec4::new(c.r, fun(c.g, Channel::Green), c.b, c.a),
+            Blue => Vec4::new(c.r, c.g, fun(c.b, Channel::Blue), c.a),
+            Alpha => Vec4::new(c.r, c.g, c.b, fun(c.a, Channel::Alpha)),
         }
     }
 }
+
+/// Remaps the input from between input black and white to between output black
+/// and white, with gamma applied in between.
+///
+/// With auto enabled the input range is the actual minimum and maximum of the
+/// input over the whole texture, which for luminance is the range of the
+/// darkest and brightest color channel.
+#[derive(Clone, Debug)]
+pub struct Levels {
+    channels: ChannelMode,
+    auto: bool,
+    in_black: f32,
+    in_white: f32,
+    gamma: f32,
+    out_black: f32,
+    out_white: f32,
+}
+
+impl Levels {
+    pub fn new(channels: ChannelMode, auto: bool) -> Box<Process> {
+        Box::new(Levels {
+            channels: channels,
+            auto: auto,
+            in_black: 0.,
+            in_white: 1.,
+            gamma: 1.,
+            out_black: 0.,
+            out_white: 1.,
+        })
+    }
+
+    fn level(&self, x: f32, black: f32, white: f32) -> f32 {
+        let t = clamp((x - black) / (white - black).max(1e-5), 0., 1.);
+        let t = t.powf(1. / self.gamma.max(0.01));
+        self.out_black + (self.out_white - self.out_black) * t
+    }
+
+    fn level_shader(&self, x: &str, black: &str, white: &str) -> String {
+        format!(
+            "mix({:?}, {:?}, pow(clamp(({x} - {b}) / max({w} - {b}, 1e-5), 0., 1.), {:?}))",
+            self.out_black,
+            self.out_white,
+            1. / self.gamma.max(0.01),
+            x = x,
+            b = black,
+            w = white
+        )
+    }
+}
+
+impl Process for Levels {
+    fn name(&self) -> &'static str {
+        "levels"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "channels" => Choice(&self.channels),
+            "auto" => Boolean(&self.auto),
+            "input black" => Float(&self.in_black),
+            "input white" => Float(&self.in_white),
+            "gamma" => Float(&self.gamma),
+            "output black" => Float(&self.out_black),
+            "output white" => Float(&self.out_white),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "channels" => Choice(&mut self.channels),
+            "auto" => Boolean(&mut self.auto),
+            "input black" => Float(&mut self.in_black),
+            "input white" => Float(&mut self.in_white),
+            "gamma" => Float(&mut self.gamma),
+            "output black" => Float(&mut self.out_black),
+            "output white" => Float(&mut self.out_white),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "channels",
+                Value::Choice("Rgb".into()),
+                "Channels of the input that are adjusted",
+            ),
+            Descriptor::new(
+                "auto",
+                Value::Boolean(false),
+                "Use the range of the input instead of input black and white",
+            ),
+            Descriptor::new(
+                "input black",
+                Value::Float(0.),
+                "Input value that becomes output black",
+            ).min(0.)
+                .max(1.)
+                .step(0.01),
+            Descriptor::new(
+                "input white",
+                Value::Float(1.),
+                "Input value that becomes output white",
+            ).min(0.)
+                .max(1.)
+                .step(0.01),
+            Descriptor::new(
+                "gamma",
+                Value::Float(1.),
+                "Brightens midtones when above 1 and darkens them when below",
+            ).min(0.01)
+                .max(10.)
+                .step(0.05),
+            Descriptor::new("output black", Value::Float(0.), "Darkest output value")
+                .min(0.)
+                .max(1.)
+                .step(0.01),
+            Descriptor::new("output white", Value::Float(1.), "Brightest output value")
+                .min(0.)
+                .max(1.)
+                .step(0.01),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let input = match ctx.input(0) {
+            Some(i) => i.to_string(),
+            None => return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?)),
+        };
+        let range = match ctx.input_range(0) {
+            Some(r) if self.auto => Some(r),
+            _ => None,
+        };
+        let mut result = String::new();
+        let value = self.channels
+            .shader(ctx, &input, &mut result, |x, channel| match range {
+                Some((ref min, ref max)) if channel == Channel::Luminance => self.level_shader(
+                    x,
+                    &format!("min(min({m}.r, {m}.g), {m}.b)", m = min),
+                    &format!("max(max({m}.r, {m}.g), {m}.b)", m = max),
+                ),
+                Some((ref min, ref max)) => {
+                    self.level_shader(x, &channel.shader(min), &channel.shader(max))
+                }
+                None => self.level_shader(
+                    x,
+                    &format!("{:?}", self.in_black),
+                    &format!("{:?}", self.in_white),
+                ),
+            });
+        result.push_str(&format!("vec4 {} = {};\n", ctx.output(0)?, value));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let input = match sampler.input(0) {
+            Some(i) => i,
+            None => return Vec4::splat(0.),
+        };
+        let range = match sampler.input_range(0) {
+            Some(r) if self.auto => Some(r),
+            _ => None,
+        };
+        self.channels.value(input, |x, channel| match range {
+            Some((min, max)) if channel == Channel::Luminance => self.level(
+                x,
+                min.r.min(min.g).min(min.b),
+                max.r.max(max.g).max(max.b),
+            ),
+            Some((min, max)) => self.level(x, channel.value(min), channel.value(max)),
+            None => self.level(x, self.in_black, self.in_white),
+        })
+    }
+    fn reduced(&self, _: u32) -> bool {
+        self.auto
+    }
+}
+
+/// Remaps the input with a smooth curve through editable points.
+#[derive(Clone, Debug)]
+pub struct Curves {
+    channels: ChannelMode,
+    points: Vec<Vec2>,
+}
+
+impl Curves {
+    pub fn new(channels: ChannelMode, points: Vec<Vec2>) -> Box<Process> {
+        Box::new(Curves {
+            channels: channels,
+            points: points,
+        })
+    }
+}
+
+impl Process for Curves {
+    fn name(&self) -> &'static str {
+        "curves"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "channels" => Choice(&self.channels),
+            "points" => Points(&self.points),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "channels" => Choice(&mut self.channels),
+            "points" => Points(&mut self.points),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "channels",
+                Value::Choice("Rgb".into()),
+                "Channels of the input that are adjusted",
+            ),
+            Descriptor::new(
+                "points",
+                Value::Points(vec![Vec2::new(0., 0.), Vec2::new(1., 1.)]),
+                "Input values and what they become, from 0 to 1",
+            ).min(0.)
+                .max(1.),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let input = match ctx.input(0) {
+            Some(i) => i.to_string(),
+            None => return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?)),
+        };
+        let mut result = String::new();
+        let value = self.channels
+            .shader(ctx, &input, &mut result, |x, _| curve_shader(&self.points, x));
+        result.push_str(&format!("vec4 {} = {};\n", ctx.output(0)?, value));
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        match sampler.input(0) {
+            Some(i) => self.channels.value(i, |x, _| curve(&self.points, x)),
+            None => Vec4::splat(0.),
+        }
+    }
+}
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(ColorModels), IterVariantNames(ColorModelNames))]
+    pub enum ColorModel {
+        Hsv,
+        Hsl,
+    }
+}
+
+impl_choice!(ColorModel);
+
+/// Conversion of a color between RGB and a color model.
+type Conversion = fn([f32; 3]) -> [f32; 3];
+
+/// Shifts hue and changes saturation and value or lightness of the input.
+///
+/// The adjustment is done on sRGB encoded colors, like image editors do.
+#[derive(Clone, Debug)]
+pub struct HueSaturation {
+    model: ColorModel,
+    hue: f32,
+    saturation: f32,
+    lightness: f32,
+}
+
+impl HueSaturation {
+    pub fn new(model: ColorModel, hue: f32, saturation: f32, lightness: f32) -> Box<Process> {
+        Box::new(HueSaturation {
+            model: model,
+            hue: hue,
+            saturation: saturation,
+            lightness: lightness,
+        })
+    }
+}
+
+impl Process for HueSaturation {
+    fn name(&self) -> &'static str {
+        "hue_saturation"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        use process::Setting::*;
+        Ok(match key {
+            "model" => Choice(&self.model),
+            "hue" => Float(&self.hue),
+            "saturation" => Float(&self.saturation),
+            "lightness" => Float(&self.lightness),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        use process::SettingMut::*;
+        Ok(match key {
+            "model" => Choice(&mut self.model),
+            "hue" => Float(&mut self.hue),
+            "saturation" => Float(&mut self.saturation),
+            "lightness" => Float(&mut self.lightness),
+            _ => return Err(SettingError::unknown(self.name(), key)),
+        })
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![
+            Descriptor::new(
+                "model",
+                Value::Choice("Hsv".into()),
+                "Whether lightness is adjusted as value or lightness",
+            ),
+            Descriptor::new("hue", Value::Float(0.), "Hue shift in degrees")
+                .min(-180.)
+                .max(180.)
+                .step(1.),
+            Descriptor::new(
+                "saturation",
+                Value::Float(0.),
+                "Amount added to saturation",
+            ).min(-1.)
+                .max(1.)
+                .step(0.01),
+            Descriptor::new(
+                "lightness",
+                Value::Float(0.),
+                "Amount added to value or lightness",
+            ).min(-1.)
+                .max(1.)
+                .step(0.01),
+        ]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        1
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let input = match ctx.input(0) {
+            Some(i) => i,
+            None => return Ok(format!("vec4 {} = vec4(0);\n", ctx.output(0)?)),
+        };
+        let (to, from) = match self.model {
+            ColorModel::Hsv => ("rgb_to_hsv", "hsv_to_rgb"),
+            ColorModel::Hsl => ("rgb_to_hsl", "hsl_to_rgb"),
+        };
+        let c = ctx.temporary();
+        Ok(format!(
+            "vec3 {c} = {}(clamp({i}.rgb, 0., 1.));\n\
+             {c} = vec3(fract({c}.x + {:?}), clamp({c}.yz + vec2({:?}, {:?}), 0., 1.));\n\
+             vec4 {} = vec4({}({c}), {i}.a);\n",
+            to,
+            self.hue / 360.,
+            self.saturation,
+            self.lightness,
+            ctx.output(0)?,
+            from,
+            c = c,
+            i = input
+        ))
+    }
+    fn evaluate(&self, sampler: &Sampler, _: u32) -> Vec4 {
+        let input = match sampler.input(0) {
+            Some(i) => i,
+            None => return Vec4::splat(0.),
+        };
+        let c: Col = input.map(|v| clamp(v, 0., 1.)).into();
+        let (to, from): (Conversion, Conversion) = match self.model {
+            ColorModel::Hsv => (rgb_to_hsv, hsv_to_rgb),
+            ColorModel::Hsl => (rgb_to_hsl, hsl_to_rgb),
+        };
+        let h = to([c.red, c.green, c.blue]);
+        let rgb = from([
+            fract(h[0] + self.hue / 360.),
+            clamp(h[1] + self.saturation, 0., 1.),
+            clamp(h[2] + self.lightness, 0., 1.),
+        ]);
+        Col::new(rgb[0], rgb[1], rgb[2], input.a).into()
+    }
+}
+
+/// Splits the input into its red, green, blue and alpha channels as grayscale outputs.
+#[derive(Clone, Debug)]
+pub struct SplitChannels;
+
+impl SplitChannels {
+    pub fn new() -> Box<Process> {
+        Box::new(SplitChannels)
+    }
+}
+
+impl Process for SplitChannels {
+    fn name(&self) -> &'static str {
+        "split_channels"
+    }
+    fn setting(&self, key: &str) -> Result<Setting, SettingError> {
+        Err(SettingError::unknown(self.name(), key))
+    }
+    fn setting_mut(&mut self, key: &str) -> Result<SettingMut, SettingError> {
+        Err(SettingError::unknown(self.name(), key))
+    }
+    fn settings(&self) -> Vec<Descriptor> {
+        vec![]
+    }
+    fn max_in(&self) -> u32 {
+        1
+    }
+    fn max_out(&self) -> u32 {
+        4
+    }
+    fn shader(&self, ctx: &mut Context) -> Result<String, ShaderError> {
+        let input = ctx.input(0);
+        let mut result = String::new();
+        for (i, channel) in [Channel::Red, Channel::Green, Channel::Blue, Channel::Alpha]
+            .iter()
+            .enumerate()
+        {
+            let value = match input {
+                Some(input) => channel.shader(&input.to_string()),
+                None => "0.".into(),
+            };
+            result.push_str(&format!(
+                "vec4 {} = vec4(vec3({}), 1.);\n",
+                ctx.output(i as u32)?,
+                value
+            ));
+        }
+        Ok(result)
+    }
+    fn evaluate(&self, sampler: &Sampler, output: u32) -> Vec4 {
+        let input = sampler.input(0).unwrap_or(Vec4::splat(0.));
+        Vec4::gray(match output {
+            0 => input.r,
+            1 => input.g,
+            2 => input.b,
+            _ => input.a,
+        })
+    }
+}
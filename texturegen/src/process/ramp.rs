@@ -0,0 +1,82 @@
+//! Color ramps interpolating between stops, shared by processes mapping values to colors.
+
+use std::cmp::Ordering;
+
+use cpu::{clamp, smoothstep, Vec4};
+use shader::col;
+use process::Stop;
+
+custom_derive! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq,
+        IterVariants(Interpolations), IterVariantNames(InterpolationNames))]
+    pub enum Interpolation {
+        Linear,
+        Smoothstep,
+        Constant,
+    }
+}
+
+impl_choice!(Interpolation);
+
+/// Stops ordered by their position, which they don't have to be in settings.
+fn sorted(stops: &[Stop]) -> Vec<Stop> {
+    let mut stops = stops.to_vec();
+    stops.sort_by(|a, b| {
+        a.position
+            .partial_cmp(&b.position)
+            .unwrap_or(Ordering::Equal)
+    });
+    stops
+}
+
+impl Interpolation {
+    /// How far `t` is from stop at `a` to the next one at `b`, from 0 to 1.
+    fn factor(&self, a: f32, b: f32, t: f32) -> f32 {
+        use self::Interpolation::*;
+        let step = |edge: f32| if t >= edge { 1. } else { 0. };
+        match *self {
+            _ if b <= a => step(a),
+            Linear => clamp((t - a) / (b - a), 0., 1.),
+            Smoothstep => smoothstep(a, b, t),
+            Constant => step(b),
+        }
+    }
+
+    fn factor_shader(&self, a: f32, b: f32, t: &str) -> String {
+        use self::Interpolation::*;
+        match *self {
+            _ if b <= a => format!("step({:?}, {})", a, t),
+            Linear => format!("clamp(({} - {:?}) / {:?}, 0., 1.)", t, a, b - a),
+            Smoothstep => format!("smoothstep({:?}, {:?}, {})", a, b, t),
+            Constant => format!("step({:?}, {})", b, t),
+        }
+    }
+}
+
+/// Color of the ramp at `t`, which is the first or last color outside of the stops.
+pub fn ramp(stops: &[Stop], t: f32, interpolation: Interpolation) -> Vec4 {
+    let stops = sorted(stops);
+    let mut result = stops
+        .first()
+        .map(|s| s.color.into())
+        .unwrap_or(Vec4::splat(0.));
+    for pair in stops.windows(2) {
+        let f = interpolation.factor(pair[0].position, pair[1].position, t);
+        result = result + (Vec4::from(pair[1].color) - result) * f;
+    }
+    result
+}
+
+/// GLSL expression for the color of the ramp at `t`, which should be a variable.
+pub fn ramp_shader(stops: &[Stop], t: &str, interpolation: Interpolation) -> String {
+    let stops = sorted(stops);
+    let mut result = stops
+        .first()
+        .map(|s| col(s.color))
+        .unwrap_or_else(|| "vec4(0)".into());
+    for pair in stops.windows(2) {
+        let f = interpolation.factor_shader(pair[0].position, pair[1].position, t);
+        result = format!("mix({}, {}, {})", result, col(pair[1].color), f);
+    }
+    result
+}
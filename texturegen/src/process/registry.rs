@@ -0,0 +1,290 @@
+use std::slice;
+
+use {Col, Vec2};
+use process::{AmbientOcclusion, Blend, BlendType, Blur, BlurType, Bricks, Channel, ChannelMode,
+              ColorModel, CombineChannels, Constant, Curvature, Curves, Derivative, EdgeDetect,
+              EdgeDetectType, FractalNoise, FractalType, Gradient, GradientMap, GradientType,
+              HueSaturation, Interpolation, Invert, Levels, Math, Noise, NormalMap, Operation,
+              PerlinNoise, Process, Select, Shape, ShapeType, SplitChannels, Stop, Stripes,
+              Transform, ValueNoise, VoronoiNoise, Warp, WarpType, WorleyMetric, WorleyMode,
+              WorleyNoise};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Input,
+    Modifier,
+    Combiner,
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    name: &'static str,
+    category: Category,
+    description: &'static str,
+    factory: fn() -> Box<Process>,
+}
+
+impl Entry {
+    /// Name of the process type, same as `Process::name` of what the factory creates.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn category(&self) -> Category {
+        self.category
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    /// Creates the process with the defaults of its setting descriptors.
+    pub fn create(&self) -> Box<Process> {
+        let mut process = (self.factory)();
+        for descriptor in process.settings() {
+            process
+                .set(descriptor.name, descriptor.default)
+                .expect("Default of a setting wasn't valid for it.");
+        }
+        process
+    }
+}
+
+/// Maps process type names to factories creating them.
+///
+/// Entries are kept in the order they were registered in, so it can be used as is for palettes.
+#[derive(Clone)]
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry { entries: vec![] }
+    }
+
+    /// Registry containing all the processes of this crate.
+    pub fn builtin() -> Registry {
+        use self::Category::*;
+        let mut registry = Registry::new();
+        registry.register("constant", Input, "Single color", || {
+            Constant::new(Col::new(1., 1., 1., 1.))
+        });
+        registry.register("blend", Combiner, "Blends two inputs together", || {
+            Blend::new(BlendType::Normal, BlendType::Normal)
+        });
+        registry.register("stripes", Input, "Checkerboard of two colors", || {
+            Stripes::new(4, 1, Col::new(1., 1., 1., 1.), Col::new(0., 0., 0., 1.))
+        });
+        registry.register("edge_detect", Modifier, "Highlights edges of the input", || {
+            EdgeDetect::new(0.5, 0.002, EdgeDetectType::Sobel)
+        });
+        registry.register("noise", Input, "Simplex noise", || Noise::new(0, 2, 2));
+        registry.register("voronoi_noise", Input, "Voronoi noise", || {
+            VoronoiNoise::new(0, 10, 10, 1., 1.)
+        });
+        registry.register(
+            "select",
+            Modifier,
+            "Selects between first and third input by brightness of the second",
+            || Select::new(0.5),
+        );
+        registry.register("invert", Modifier, "Inverts colors or alpha", Invert::new);
+        registry.register("fractal_noise", Input, "Layered simplex noise", || {
+            FractalNoise::new(0, 2, 2, 5, 2., 0.5, FractalType::Fbm)
+        });
+        registry.register("perlin_noise", Input, "Gradient noise", || {
+            PerlinNoise::new(0, 4, 4)
+        });
+        registry.register("value_noise", Input, "Interpolated random values", || {
+            ValueNoise::new(0, 4, 4)
+        });
+        registry.register("worley_noise", Input, "Cellular noise", || {
+            WorleyNoise::new(0, 8, 8, WorleyMetric::Euclidean, WorleyMode::F1)
+        });
+        registry.register("gradient", Input, "Color ramp along a shape", || {
+            Gradient::new(
+                GradientType::Linear,
+                0.,
+                vec![
+                    Stop::new(0., Col::new(0., 0., 0., 1.)),
+                    Stop::new(1., Col::new(1., 1., 1., 1.)),
+                ],
+            )
+        });
+        registry.register("gradient_map", Modifier, "Colors the input with a color ramp", || {
+            GradientMap::new(
+                Channel::Luminance,
+                Interpolation::Linear,
+                vec![
+                    Stop::new(0., Col::new(0., 0., 0., 1.)),
+                    Stop::new(1., Col::new(1., 1., 1., 1.)),
+                ],
+            )
+        });
+        registry.register("shape", Input, "Antialiased geometric shape", || {
+            Shape::new(ShapeType::Circle)
+        });
+        registry.register("bricks", Input, "Tiles laid in rows", || {
+            Bricks::new(0, 8, 4, 0.5, 0.01, 0.02, 0.2)
+        });
+        registry.register(
+            "transform",
+            Modifier,
+            "Moves, scales, rotates or repeats the input",
+            Transform::new,
+        );
+        registry.register("warp", Modifier, "Moves the first input by the second one", || {
+            Warp::new(0.1, 0., WarpType::Directional)
+        });
+        registry.register("blur", Modifier, "Softens the input", || {
+            Blur::new(0.01, 0., BlurType::Gaussian)
+        });
+        registry.register("normal_map", Modifier, "Normals of the input as height", || {
+            NormalMap::new(0.05, 0.002, false, Derivative::Sobel)
+        });
+        registry.register(
+            "ambient_occlusion",
+            Modifier,
+            "Darkens places of the input as height surrounded by higher ones",
+            || AmbientOcclusion::new(0.05, 8, 1.),
+        );
+        registry.register(
+            "curvature",
+            Modifier,
+            "Convex and concave places of the input as height",
+            || Curvature::new(0.005, 1.),
+        );
+        registry.register("levels", Modifier, "Remaps the range of input values", || {
+            Levels::new(ChannelMode::Rgb, false)
+        });
+        registry.register("curves", Modifier, "Remaps input values with a curve", || {
+            Curves::new(ChannelMode::Rgb, vec![Vec2::new(0., 0.), Vec2::new(1., 1.)])
+        });
+        registry.register(
+            "hue_saturation",
+            Modifier,
+            "Shifts hue and changes saturation and lightness",
+            || HueSaturation::new(ColorModel::Hsv, 0., 0., 0.),
+        );
+        registry.register(
+            "split_channels",
+            Modifier,
+            "Red, green, blue and alpha of the input as separate outputs",
+            SplitChannels::new,
+        );
+        registry.register(
+            "combine_channels",
+            Combiner,
+            "Packs channels of four inputs into one color",
+            || {
+                CombineChannels::new(
+                    Channel::Luminance,
+                    Channel::Luminance,
+                    Channel::Luminance,
+                    Channel::Luminance,
+                )
+            },
+        );
+        registry.register("math", Combiner, "Math operation on up to three inputs", || {
+            Math::new(Operation::Add, 0., 0., 0.)
+        });
+        registry
+    }
+
+    /// Registers new process type, returning the entry it replaced if the name was already taken.
+    ///
+    /// Settings of what the factory creates are overwritten with the defaults of their
+    /// descriptors, so the descriptors are where the defaults are defined.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        category: Category,
+        description: &'static str,
+        factory: fn() -> Box<Process>,
+    ) -> Option<Entry> {
+        let entry = Entry {
+            name: name,
+            category: category,
+            description: description,
+            factory: factory,
+        };
+        if let Some(e) = self.entries.iter_mut().find(|e| e.name == name) {
+            return Some(::std::mem::replace(e, entry));
+        }
+        self.entries.push(entry);
+        None
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<Process>> {
+        self.get(name).map(Entry::create)
+    }
+
+    pub fn iter(&self) -> slice::Iter<Entry> {
+        self.entries.iter()
+    }
+
+    /// Entries whose name or description contains the query, ignoring case.
+    ///
+    /// Entries matched by name come before the ones only matched by description, both in the
+    /// order they were registered in.
+    pub fn search(&self, query: &str) -> Vec<&Entry> {
+        let query = query.to_lowercase();
+        let (mut result, described): (Vec<_>, Vec<_>) = self.entries
+            .iter()
+            .filter(|e| {
+                e.name.contains(&query[..]) || e.description.to_lowercase().contains(&query[..])
+            })
+            .partition(|e| e.name.contains(&query[..]));
+        result.extend(described);
+        result
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(entries: Vec<&Entry>) -> Vec<&'static str> {
+        entries.into_iter().map(Entry::name).collect()
+    }
+
+    #[test]
+    fn search_finds_every_entry() {
+        let registry = Registry::builtin();
+        assert_eq!(registry.search("").len(), registry.iter().count());
+        for entry in registry.iter() {
+            assert_eq!(registry.search(&entry.name().to_uppercase())[0].name(), entry.name());
+        }
+    }
+
+    #[test]
+    fn created_processes_have_default_settings() {
+        for entry in Registry::builtin().iter() {
+            let process = entry.create();
+            assert_eq!(process.name(), entry.name());
+            for descriptor in process.settings() {
+                let value = process.setting(descriptor.name).unwrap().to_value();
+                assert_eq!(value, descriptor.default, "{}.{}", entry.name(), descriptor.name);
+            }
+        }
+    }
+
+    #[test]
+    fn search_prefers_names() {
+        let registry = Registry::builtin();
+        let found = names(registry.search("blur"));
+        assert_eq!(found[0], "blur");
+        assert!(names(registry.search("nothing like this")).is_empty());
+    }
+}
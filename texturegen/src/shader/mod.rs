@@ -1,9 +1,11 @@
+use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::collections::hash_map::{self, HashMap};
 
 use palette::rgb::Rgba;
 
 use Col;
+use dag::Port;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Source {
@@ -13,6 +15,7 @@ pub struct Source {
 
 pub struct Shader {
     vertex_snippets: Vec<String>,
+    functions: Vec<String>,
     fragment_snippets: Vec<String>,
 }
 
@@ -20,6 +23,7 @@ impl Shader {
     pub fn new() -> Shader {
         Shader {
             vertex_snippets: vec![],
+            functions: vec![],
             fragment_snippets: vec![],
         }
     }
@@ -28,6 +32,11 @@ impl Shader {
         self.vertex_snippets.push(snippet.into());
     }
 
+    /// Adds definition outside of `main` to the fragment shader.
+    pub fn add_function<S: Into<String>>(&mut self, function: S) {
+        self.functions.push(function.into());
+    }
+
     pub fn add_fragment<S: Into<String>>(&mut self, snippet: S) {
         self.fragment_snippets.push(snippet.into());
     }
@@ -50,18 +59,33 @@ impl Shader {
         fragment.push_str("#version 140\n");
         fragment.push_str("in vec2 v_tex_coords;\n");
         fragment.push_str("out vec4 color;\n");
+        fragment.push_str("const vec4 one = vec4(1);\n");
         fragment.push_str(
             r#"
-        //  <https://www.shadertoy.com/view/Xd23Dh>
-        //  by inigo quilez <http://iquilezles.org/www/articles/voronoise/voronoise.htm>
-        vec3 hash3(vec2 p) {
-            vec3 q = vec3(dot(p, vec2(127.1, 311.7)),
-                          dot(p, vec2(269.5, 183.3)),
-                          dot(p, vec2(419.2, 371.9)));
-            return fract(sin(q) * 43758.5453);
+        // pcg3d from "Hash Functions for GPU Rendering" by Mark Jarzynski and Marc Olano.
+        // <http://jcgt.org/published/0009/03/02/>
+        uvec3 pcg3d(uvec3 v) {
+            v = v * 1664525u + 1013904223u;
+            v.x += v.y * v.z;
+            v.y += v.z * v.x;
+            v.z += v.x * v.y;
+            v ^= v >> 16u;
+            v.x += v.y * v.z;
+            v.y += v.z * v.x;
+            v.z += v.x * v.y;
+            return v;
         }
 
-        float iqnoise(in vec2 x, float u, float v) {
+        // Random values in [0, 1) for integer point `p`. Being calculated with
+        // integers, these are exactly the same on every backend.
+        vec3 hash3(vec2 p, uint seed) {
+            uvec3 h = pcg3d(uvec3(ivec2(p), seed));
+            return vec3(h >> 8u) / 16777216.;
+        }
+
+        //  <https://www.shadertoy.com/view/Xd23Dh>
+        //  by inigo quilez <http://iquilezles.org/www/articles/voronoise/voronoise.htm>
+        float iqnoise(in vec2 x, float u, float v, uint seed) {
             vec2 floor = floor(x);
             vec2 fract = fract(x);
 
@@ -72,7 +96,7 @@ impl Shader {
             for(int x = -2; x <= 2; x++) {
                 for(int y = -2; y <= 2; y++) {
                     vec2 offset = vec2(float(x), float(y));
-                    vec3 o = hash3(floor + offset) * vec3(u, u, 1.);
+                    vec3 o = hash3(floor + offset, seed) * vec3(u, u, 1.);
                     vec2 r = offset - fract + o.xy;
                     float d = dot(r, r);
                     float ww = pow(1. - smoothstep(0., 1.414, sqrt(d)), k);
@@ -104,54 +128,193 @@ impl Shader {
             return mod289(((x * 34.) + 1.) * x);
         }
 
-        float snoise(float seed, vec2 v) {
-            const vec4 C = vec4(0.211324865405187,  // (3.-sqrt(3.))/6.
-                                0.366025403784439,  // 0.5*(sqrt(3.)-1.)
-                                -0.577350269189626,  // -1. + 2. * C.x
-                                0.024390243902439); // 1. / 41.
-            // First corner
-            vec2 i  = floor(v + dot(v, C.yy));
-            vec2 x0 = v - i + dot(i, C.xx);
-
-            // Other corners
-            vec2 i1;
-            //i1.x = step(x0.y, x0.x); // x0.x > x0.y ? 1. : 0.
-            //i1.y = 1. - i1.x;
-            i1 = (x0.x > x0.y) ? vec2(1., 0.) : vec2(0., 1.);
-            // x0 = x0 - 0. + 0. * C.xx ;
-            // x1 = x0 - i1 + 1. * C.xx ;
-            // x2 = x0 - 1. + 2. * C.xx ;
-            vec4 x12 = x0.xyxy + C.xxzz;
-            x12.xy -= i1;
-
-            // Permutations
-            i = mod289(i); // Avoid truncation effects in permutation
-            vec3 p = permute(permute(i.y + vec3(0., i1.y, 1.)) + i.x + vec3(0., i1.x, 1.));
-            p = permute(p + vec3(seed));
-
-            vec3 m = max(0.5 - vec3(dot(x0, x0), dot(x12.xy, x12.xy), dot(x12.zw, x12.zw)), 0.);
-            m = m * m;
-            m = m * m;
-
-            // Gradients: 41 points uniformly over a line, mapped onto a diamond.
-            // The ring size 17*17 = 289 is close to a multiple of 41 (41*7 = 287)
-            vec3 x = 2. * fract(p * C.www) - 1.;
-            vec3 h = abs(x) - 0.5;
-            vec3 ox = floor(x + 0.5);
-            vec3 a0 = x - ox;
-
-            // Normalise gradients implicitly by scaling m
-            // Approximation of: m *= inversesqrt(a0 * a0 + h * h);
-            m *= 1.79284291400159 - 0.85373472095314 * (a0 * a0 + h * h);
-
-            // Compute final noise value at P
-            vec3 g;
-            g.x = a0.x * x0.x + h.x * x0.y;
-            g.yz = a0.yz * x12.xz + h.yz * x12.yw;
-            return 130. * dot(m, g) * 0.5 + 0.5;
+        // Voronoi noise which repeats every `period` cells.
+        float iqnoise_tiled(in vec2 x, float u, float v, vec2 period, uint seed) {
+            vec2 floor = floor(x);
+            vec2 fract = fract(x);
+
+            float k = 1. + 63. * pow(1. - v, 4.);
+
+            float va = 0.;
+            float wt = 0.;
+            for(int x = -2; x <= 2; x++) {
+                for(int y = -2; y <= 2; y++) {
+                    vec2 offset = vec2(float(x), float(y));
+                    vec3 o = hash3(mod(floor + offset, period), seed) * vec3(u, u, 1.);
+                    vec2 r = offset - fract + o.xy;
+                    float d = dot(r, r);
+                    float ww = pow(1. - smoothstep(0., 1.414, sqrt(d)), k);
+                    va += o.z * ww;
+                    wt += ww;
+                }
+            }
+            return va / wt;
+        }
+
+        // Lattice point wrapped to the period, components of which are zero when not tiling.
+        vec2 wrap(vec2 p, vec2 period) {
+            return vec2(period.x > 0. ? mod(p.x, period.x) : p.x,
+                        period.y > 0. ? mod(p.y, period.y) : p.y);
+        }
+
+        // Simplex noise on a grid which can be wrapped, repeating every `period`
+        // units where `period.x` is an integer and `period.y` an even integer.
+        // Based on psrdnoise by Stefan Gustavson and Ian McEwan.
+        // Distributed under the MIT License. https://github.com/stegu/psrdnoise
+        float snoise(uint seed, vec2 x, vec2 period) {
+            // Different seeds hash lattice points as if they were elsewhere, which keeps the period
+            vec2 offset = floor(hash3(vec2(0.), seed).xy * 289.);
+            // Transform to the grid where simplices are halves of unit squares
+            vec2 uv = vec2(x.x + x.y * 0.5, x.y);
+            vec2 i0 = floor(uv);
+            vec2 f0 = fract(uv);
+            float cmp = step(f0.y, f0.x);
+            vec2 o1 = vec2(cmp, 1. - cmp);
+
+            // Corners back in texture space
+            vec2 v0 = vec2(i0.x - i0.y * 0.5, i0.y);
+            vec2 v1 = vec2(v0.x + o1.x - o1.y * 0.5, v0.y + o1.y);
+            vec2 v2 = vec2(v0.x + 0.5, v0.y + 1.);
+            vec2 x0 = x - v0;
+            vec2 x1 = x - v1;
+            vec2 x2 = x - v2;
+
+            // Wrap corners to the period and round back to the grid
+            vec2 w0 = wrap(v0, period);
+            vec2 w1 = wrap(v1, period);
+            vec2 w2 = wrap(v2, period);
+            vec3 xw = vec3(w0.x, w1.x, w2.x);
+            vec3 yw = vec3(w0.y, w1.y, w2.y);
+            vec3 iu = floor(xw + 0.5 * yw + 0.5);
+            vec3 iv = floor(yw + 0.5);
+
+            vec3 p = permute(permute(mod289(iv + offset.y)) + mod289(iu + offset.x));
+            vec3 psi = p * 0.07482;
+            vec3 gx = cos(psi);
+            vec3 gy = sin(psi);
+
+            vec3 w = max(0.8 - vec3(dot(x0, x0), dot(x1, x1), dot(x2, x2)), 0.);
+            w = w * w;
+            w = w * w;
+            vec3 g = vec3(dot(vec2(gx.x, gy.x), x0),
+                          dot(vec2(gx.y, gy.y), x1),
+                          dot(vec2(gx.z, gy.z), x2));
+            return 10.9 * dot(w, g) * 0.5 + 0.5;
+        }
+
+        vec2 fade(vec2 t) {
+            return t * t * t * (t * (t * 6. - 15.) + 10.);
+        }
+
+        float perlin_corner(vec2 i, vec2 f, vec2 corner, vec2 period, uint seed) {
+            float a = hash3(wrap(i + corner, period), seed).x * 6.28318530718;
+            return dot(vec2(cos(a), sin(a)), f - corner);
+        }
+
+        float perlin_noise(vec2 x, vec2 period, uint seed) {
+            vec2 i = floor(x);
+            vec2 f = fract(x);
+            vec2 u = fade(f);
+            float n = mix(mix(perlin_corner(i, f, vec2(0., 0.), period, seed),
+                              perlin_corner(i, f, vec2(1., 0.), period, seed), u.x),
+                          mix(perlin_corner(i, f, vec2(0., 1.), period, seed),
+                              perlin_corner(i, f, vec2(1., 1.), period, seed), u.x), u.y);
+            return n * 0.70710678 + 0.5;
+        }
+
+        float value_noise(vec2 x, vec2 period, uint seed) {
+            vec2 i = floor(x);
+            vec2 u = fade(fract(x));
+            return mix(mix(hash3(wrap(i, period), seed).x,
+                           hash3(wrap(i + vec2(1., 0.), period), seed).x, u.x),
+                       mix(hash3(wrap(i + vec2(0., 1.), period), seed).x,
+                           hash3(wrap(i + vec2(1., 1.), period), seed).x, u.x), u.y);
+        }
+
+        // Distances to the closest and second closest cell points and random value
+        // of the closest cell. Metric is 0 for euclidean, 1 for manhattan and 2 for
+        // chebyshev distance.
+        vec3 worley_noise(vec2 x, vec2 period, uint seed, int metric) {
+            vec2 i = floor(x);
+            vec2 f = fract(x);
+            vec3 result = vec3(8., 8., 0.);
+            for(int cy = -2; cy <= 2; cy++) {
+                for(int cx = -2; cx <= 2; cx++) {
+                    vec2 offset = vec2(float(cx), float(cy));
+                    vec3 h = hash3(wrap(i + offset, period), seed);
+                    vec2 r = offset + h.xy - f;
+                    float d = metric == 0 ? length(r)
+                            : metric == 1 ? abs(r.x) + abs(r.y)
+                            : max(abs(r.x), abs(r.y));
+                    if(d < result.x) {
+                        result = vec3(d, result.x, h.z);
+                    } else if(d < result.y) {
+                        result.y = d;
+                    }
+                }
+            }
+            return result;
+        }
+
+        // Hue, saturation and value of a color, all from 0 to 1.
+        vec3 rgb_to_hsv(vec3 c) {
+            float high = max(max(c.r, c.g), c.b);
+            float d = high - min(min(c.r, c.g), c.b);
+            float h = 0.;
+            if(d > 0.) {
+                if(high == c.r) {
+                    h = mod((c.g - c.b) / d, 6.);
+                } else if(high == c.g) {
+                    h = (c.b - c.r) / d + 2.;
+                } else {
+                    h = (c.r - c.g) / d + 4.;
+                }
+            }
+            return vec3(h / 6., high > 0. ? d / high : 0., high);
+        }
+
+        vec3 hsv_to_rgb(vec3 c) {
+            vec3 k = mod(vec3(5., 3., 1.) + c.x * 6., 6.);
+            return c.z - c.z * c.y * clamp(min(k, 4. - k), 0., 1.);
+        }
+
+        // Hue, saturation and lightness of a color, all from 0 to 1.
+        vec3 rgb_to_hsl(vec3 c) {
+            vec3 hsv = rgb_to_hsv(c);
+            float l = hsv.z * (1. - hsv.y / 2.);
+            float s = l > 0. && l < 1. ? (hsv.z - l) / min(l, 1. - l) : 0.;
+            return vec3(hsv.x, s, l);
+        }
+
+        vec3 hsl_to_rgb(vec3 c) {
+            vec3 k = mod(vec3(0., 8., 4.) + c.x * 12., 12.);
+            float a = c.y * min(c.z, 1. - c.z);
+            return c.z - a * clamp(min(k - 3., 9. - k), -1., 1.);
+        }
+
+        // Signed distance to a box with half size b and corners rounded by r.
+        float box_sdf(vec2 p, vec2 b, float r) {
+            vec2 e = abs(p) - b + r;
+            return length(max(e, 0.)) + min(max(e.x, e.y), 0.) - r;
+        }
+
+        // Signed distance to a star with n points on the unit circle, one of them
+        // pointing up, and inner vertices at radius inner, which is a regular
+        // polygon for inner = cos(pi / n).
+        float star_sdf(vec2 p, float n, float inner) {
+            float an = 3.14159265359 / n;
+            float bn = mod(atan(p.x, p.y) + an, 2. * an) - an;
+            p = length(p) * vec2(cos(bn), abs(sin(bn)));
+            vec2 e = inner * vec2(cos(an), sin(an)) - vec2(1., 0.);
+            vec2 w = p - vec2(1., 0.);
+            vec2 d = w - e * clamp(dot(w, e) / dot(e, e), 0., 1.);
+            return -length(d) * sign(e.x * w.y - e.y * w.x);
         }
         "#,
         );
+        for function in self.functions {
+            fragment.push_str(&function);
+        }
         fragment.push_str("void main() {\n");
         for snippet in self.fragment_snippets {
             fragment.push_str(&snippet);
@@ -165,11 +328,37 @@ impl Shader {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShaderError {
+    /// Process asked for an output port it doesn't have.
+    MissingOutput { node: usize, index: u32 },
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            ShaderError::MissingOutput { node, index } => {
+                write!(fmt, "Node {} doesn't have output {}", node, index)
+            }
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn description(&self) -> &str {
+        "Generating shader failed"
+    }
+}
+
 pub struct Context {
     id: usize,
     inputs: HashMap<u32, Identifier>,
+    sources: HashMap<u32, Identifier>,
     outputs: HashMap<u32, Identifier>,
+    ranges: HashMap<u32, Identifier>,
     temps: u32,
+    tiling: bool,
+    stage: u32,
 }
 
 pub struct Inputs<'a>(hash_map::Iter<'a, u32, Identifier>);
@@ -182,22 +371,17 @@ impl<'a> Iterator for Inputs<'a> {
 }
 
 impl Context {
-    pub fn new<I: IntoIterator<Item = u32>>(id: usize, inputs: I, outputs: u32) -> Context {
-        Context {
+    /// Creates context for node `id` whose inputs are connected to the given parent ports.
+    pub fn new<I: IntoIterator<Item = (u32, Port<u32>)>>(
+        id: usize,
+        inputs: I,
+        outputs: u32,
+        tiling: bool,
+    ) -> Context {
+        let mut context = Context {
             id: id,
-            inputs: inputs
-                .into_iter()
-                .map(|i| {
-                    (
-                        i,
-                        Identifier {
-                            id: id,
-                            itype: Type::Input,
-                            index: i,
-                        },
-                    )
-                })
-                .collect(),
+            inputs: HashMap::new(),
+            sources: HashMap::new(),
             outputs: (0..outputs)
                 .map(|i| {
                     (
@@ -210,19 +394,105 @@ impl Context {
                     )
                 })
                 .collect(),
+            ranges: HashMap::new(),
             temps: 0,
+            tiling: tiling,
+            stage: 0,
+        };
+        for (i, source) in inputs {
+            context.inputs.insert(
+                i,
+                Identifier {
+                    id: id,
+                    itype: Type::Input,
+                    index: i,
+                },
+            );
+            context.sources.insert(
+                i,
+                Identifier {
+                    id: source.node.index(),
+                    itype: Type::Node,
+                    index: source.port,
+                },
+            );
         }
+        context
+    }
+
+    /// Makes the ranges of the given inputs available from the reduced textures of their parent
+    /// ports.
+    pub fn with_ranges<I: IntoIterator<Item = (u32, Port<u32>)>>(mut self, ranges: I) -> Context {
+        for (i, source) in ranges {
+            self.ranges.insert(i, range(source.node.index(), source.port));
+        }
+        self
+    }
+
+    /// Sets which of the `Process::stages` is being built, reading the earlier one from its
+    /// uniform.
+    pub fn with_stage(mut self, stage: u32) -> Context {
+        self.stage = stage;
+        self
+    }
+
+    /// Whether the output has to wrap seamlessly at the texture edges.
+    pub fn tiling(&self) -> bool {
+        self.tiling
+    }
+
+    /// Stage of the process being built, counting from 0.
+    pub fn stage(&self) -> u32 {
+        self.stage
+    }
+
+    /// Texture coordinates the node is being evaluated at.
+    pub fn coords(&self) -> &'static str {
+        "coords"
     }
 
     pub fn input(&self, index: u32) -> Option<Identifier> {
         self.inputs.get(&index).map(Clone::clone)
     }
 
-    pub fn first_input(&self) -> Identifier {
-        self.inputs()
-            .next()
-            .expect("There wasn't any inputs to take first of")
-            .1
+    /// Expression evaluating input `index` at texture coordinates given by `coords`.
+    ///
+    /// Ancestors that aren't rendered by an earlier pass are evaluated at the
+    /// same coordinates, so this can remap the coordinates of a whole subgraph.
+    pub fn input_at(&self, index: u32, coords: &str) -> Option<String> {
+        self.sources
+            .get(&index)
+            .map(|f| format!("{}({})", f, coords))
+    }
+
+    /// Expressions for the minimum and maximum of input `index` over the whole texture.
+    ///
+    /// These are only known when the process reports the input as `reduced` and
+    /// the shader is a pass of a schedule, otherwise they are 0 and 1.
+    pub fn input_range(&self, index: u32) -> Option<(String, String)> {
+        if !self.inputs.contains_key(&index) {
+            return None;
+        }
+        Some(match self.ranges.get(&index) {
+            Some(r) => (
+                format!("texelFetch({}, ivec2(0, 0), 0)", r),
+                format!("texelFetch({}, ivec2(1, 0), 0)", r),
+            ),
+            None => ("vec4(0)".into(), "vec4(1)".into()),
+        })
+    }
+
+    /// Expression reading the result of the previous stage at texture coordinates given by
+    /// `coords`, or `None` in the first stage.
+    pub fn previous_at(&self, coords: &str) -> Option<String> {
+        if self.stage == 0 {
+            return None;
+        }
+        Some(format!("texture({}, {})", stage(self.id, self.stage - 1), coords))
+    }
+
+    pub fn first_input(&self) -> Option<Identifier> {
+        self.inputs().next().map(|i| i.1)
     }
 
     pub fn inputs(&self) -> Inputs {
@@ -233,10 +503,14 @@ impl Context {
         self.inputs.len()
     }
 
-    pub fn output(&self, index: u32) -> Identifier {
-        *self.outputs
+    pub fn output(&self, index: u32) -> Result<Identifier, ShaderError> {
+        self.outputs
             .get(&index)
-            .expect(&format!("There wasn't output for index: {}", index))
+            .cloned()
+            .ok_or(ShaderError::MissingOutput {
+                node: self.id,
+                index: index,
+            })
     }
 
     pub fn temporary(&mut self) -> Identifier {
@@ -255,6 +529,10 @@ enum Type {
     Input,
     Temporary,
     Output,
+    Node,
+    Texture,
+    Range,
+    Stage,
 }
 
 impl Display for Type {
@@ -264,6 +542,10 @@ impl Display for Type {
             Input => "in",
             Temporary => "tmp",
             Output => "out",
+            Node => "node",
+            Texture => "tex",
+            Range => "range",
+            Stage => "stage",
         }.fmt(fmt)
     }
 }
@@ -281,6 +563,43 @@ impl Display for Identifier {
     }
 }
 
+/// Name of the function evaluating output `port` of node `id`.
+pub fn function(id: usize, port: u32) -> Identifier {
+    Identifier {
+        id: id,
+        itype: Type::Node,
+        index: port,
+    }
+}
+
+/// Name of the sampler uniform holding output `port` of node `id` rendered by an earlier pass.
+pub fn texture(id: usize, port: u32) -> Identifier {
+    Identifier {
+        id: id,
+        itype: Type::Texture,
+        index: port,
+    }
+}
+
+/// Name of the sampler uniform holding the minimum and maximum of output `port` of node `id`
+/// rendered by an earlier pass, in the left and right texel of a 2x1 texture.
+pub fn range(id: usize, port: u32) -> Identifier {
+    Identifier {
+        id: id,
+        itype: Type::Range,
+        index: port,
+    }
+}
+
+/// Name of the sampler uniform holding stage `index` of node `id` rendered by an earlier pass.
+pub fn stage(id: usize, index: u32) -> Identifier {
+    Identifier {
+        id: id,
+        itype: Type::Stage,
+        index: index,
+    }
+}
+
 pub fn col(c: Col) -> String {
     let c: Rgba = c.into();
     format!("vec4({}, {}, {}, {})", c.red, c.green, c.blue, c.alpha)